@@ -0,0 +1,26 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use chip_8::chip8::Chip8;
+
+// Instructions per step, enough for the frame overhead (timers, rewind) not to dominate
+const CYCLES_PER_FRAME: u32 = 1000;
+
+/** Pong runs on its own (the second paddle and the ball) without any key pressed */
+fn step(c: &mut Criterion) {
+    let mut chip8 = Chip8::with_seed(1);
+    chip8
+        .load_game_from_bytes(include_bytes!("../games/pong2.c8"))
+        .unwrap();
+    chip8.set_cycles_per_frame(CYCLES_PER_FRAME);
+
+    let mut group = c.benchmark_group("execute");
+    // Reported as instructions per second
+    group.throughput(Throughput::Elements(u64::from(CYCLES_PER_FRAME)));
+    group.bench_function("pong2", |b| b.iter(|| chip8.step()));
+    chip8.set_decode_cache(true);
+    group.bench_function("pong2 decode cache", |b| b.iter(|| chip8.step()));
+    group.finish();
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
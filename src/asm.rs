@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+
+use crate::chip8::LOAD_ADDRESS;
+
+/** An instruction operand: a register, one of the named operands, or a number/label address */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    V(u16),
+    I,
+    // [I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    // RPL user flags
+    R,
+    // LONG, the address follows as a DW
+    Long,
+    // XO-CHIP audio pitch
+    Pitch,
+    Number(u16),
+}
+
+/** Splits a source line into its label, mnemonic and operands. Operands are separated by
+ * commas and/or spaces, and comments start with ';' */
+fn parse_line(line: &str) -> (Option<&str>, Option<&str>, Vec<&str>) {
+    let code = line.split(';').next().unwrap_or("").trim();
+    let (label, code) = match code.split_once(':') {
+        Some((label, rest)) => (Some(label.trim()), rest),
+        None => (None, code),
+    };
+    let mut tokens = code
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty());
+    (label, tokens.next(), tokens.collect())
+}
+
+/** Parses 0x1F, #1F, 0b11111 or 31 */
+fn parse_number(token: &str) -> Option<u16> {
+    if let Some(hex) = token.strip_prefix("0x").or_else(|| token.strip_prefix('#')) {
+        u16::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = token.strip_prefix("0b") {
+        u16::from_str_radix(binary, 2).ok()
+    } else {
+        token.parse().ok()
+    }
+}
+
+fn parse_operand(token: &str, labels: &HashMap<&str, u16>) -> Result<Operand, String> {
+    let operand = match token.to_ascii_uppercase().as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::Hf,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        "LONG" => Operand::Long,
+        "PITCH" => Operand::Pitch,
+        register if register.len() == 2 && register.starts_with('V') => {
+            match u16::from_str_radix(&register[1..], 16) {
+                Ok(x) => Operand::V(x),
+                Err(_) => return Err(format!("invalid register: {}", token)),
+            }
+        }
+        _ => match parse_number(token).or_else(|| labels.get(token).copied()) {
+            Some(number) => Operand::Number(number),
+            None => return Err(format!("unknown label: {}", token)),
+        },
+    };
+    Ok(operand)
+}
+
+/** A label must not read as a register, an operand keyword or a number, they would shadow it */
+fn is_label_name(name: &str) -> bool {
+    matches!(parse_operand(name, &HashMap::new()), Err(e) if e.starts_with("unknown label"))
+}
+
+fn byte(value: u16) -> Result<u16, String> {
+    if value > 0xFF {
+        return Err(format!("{:#x} does not fit in a byte", value));
+    }
+    Ok(value)
+}
+
+fn address(value: u16) -> Result<u16, String> {
+    if value > 0xFFF {
+        return Err(format!("{:#x} is not a 12 bit address", value));
+    }
+    Ok(value)
+}
+
+fn nibble(value: u16) -> Result<u16, String> {
+    if value > 0xF {
+        return Err(format!("{:#x} does not fit in a nibble", value));
+    }
+    Ok(value)
+}
+
+/** Encodes one instruction into its opcode */
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<u16, String> {
+    use Operand::*;
+
+    let op_code = match (mnemonic, operands) {
+        ("SYS", [Number(nnn)]) => address(*nnn)?,
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SCD", [Number(n)]) => 0x00C0 | nibble(*n)?,
+        ("SCR", []) => 0x00FB,
+        ("SCL", []) => 0x00FC,
+        ("EXIT", []) => 0x00FD,
+        ("LOW", []) => 0x00FE,
+        ("HIGH", []) => 0x00FF,
+        ("JP", [Number(nnn)]) => 0x1000 | address(*nnn)?,
+        ("JP", [V(0), Number(nnn)]) => 0xB000 | address(*nnn)?,
+        ("CALL", [Number(nnn)]) => 0x2000 | address(*nnn)?,
+        ("SE", [V(x), Number(nn)]) => 0x3000 | x << 8 | byte(*nn)?,
+        ("SNE", [V(x), Number(nn)]) => 0x4000 | x << 8 | byte(*nn)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+        ("LD", [V(x), Number(nn)]) => 0x6000 | x << 8 | byte(*nn)?,
+        ("ADD", [V(x), Number(nn)]) => 0x7000 | x << 8 | byte(*nn)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+        ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+        ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+        ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+        ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+        ("LD", [I, Number(nnn)]) => 0xA000 | address(*nnn)?,
+        ("LD", [I, Long]) => 0xF000,
+        ("PLANE", [Number(n)]) => 0xF001 | nibble(*n)? << 8,
+        ("AUDIO", []) => 0xF002,
+        ("LD", [Pitch, V(x)]) => 0xF03A | x << 8,
+        ("RND", [V(x), Number(nn)]) => 0xC000 | x << 8 | byte(*nn)?,
+        ("DRW", [V(x), V(y), Number(n)]) => 0xD000 | x << 8 | y << 4 | nibble(*n)?,
+        ("SKP", [V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+        ("LD", [V(x), Dt]) => 0xF007 | x << 8,
+        ("LD", [V(x), K]) => 0xF00A | x << 8,
+        ("LD", [Dt, V(x)]) => 0xF015 | x << 8,
+        ("LD", [St, V(x)]) => 0xF018 | x << 8,
+        ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+        ("LD", [F, V(x)]) => 0xF029 | x << 8,
+        ("LD", [Hf, V(x)]) => 0xF030 | x << 8,
+        ("LD", [B, V(x)]) => 0xF033 | x << 8,
+        ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+        ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+        ("LD", [R, V(x)]) => 0xF075 | x << 8,
+        ("LD", [V(x), R]) => 0xF085 | x << 8,
+        ("DW", [Number(word)]) => *word,
+        _ => return Err(format!("invalid instruction: {} {:?}", mnemonic, operands)),
+    };
+    Ok(op_code)
+}
+
+/** Assembles a program in the mnemonic syntax of the disassembler, e.g. "LD V0, 0x0A",
+ * "DRW V0 V1 5" or "JP loop". Labels ("loop:") address the program as loaded at 0x200.
+ * DB lists raw bytes and DW a raw 16 bit word */
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    // First pass: label addresses
+    let mut labels = HashMap::new();
+    let mut address = LOAD_ADDRESS;
+    for (line_number, line) in source.lines().enumerate() {
+        let (label, mnemonic, operands) = parse_line(line);
+        if let Some(label) = label {
+            if !is_label_name(label) {
+                return Err(format!(
+                    "line {}: reserved label name: {}",
+                    line_number + 1,
+                    label
+                ));
+            }
+            let Ok(label_address) = u16::try_from(address) else {
+                return Err(format!(
+                    "line {}: label {} at {:#x} is past the end of memory",
+                    line_number + 1,
+                    label,
+                    address
+                ));
+            };
+            if labels.insert(label, label_address).is_some() {
+                return Err(format!(
+                    "line {}: duplicate label: {}",
+                    line_number + 1,
+                    label
+                ));
+            }
+        }
+        address += match mnemonic {
+            Some(mnemonic) if mnemonic.eq_ignore_ascii_case("DB") => operands.len(),
+            Some(_) => 2,
+            None => 0,
+        };
+    }
+
+    // Second pass: encoding
+    let mut program = Vec::new();
+    for (line_number, line) in source.lines().enumerate() {
+        let (_, mnemonic, operands) = parse_line(line);
+        let Some(mnemonic) = mnemonic else {
+            continue;
+        };
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let encoded = operands
+            .iter()
+            .map(|operand| parse_operand(operand, &labels))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|operands| {
+                if mnemonic == "DB" {
+                    operands
+                        .iter()
+                        .map(|operand| match operand {
+                            Operand::Number(value) => Ok(byte(*value)?.to_be_bytes()[1]),
+                            _ => Err(format!("invalid byte: {:?}", operand)),
+                        })
+                        .collect()
+                } else {
+                    Ok(encode(&mnemonic, &operands)?.to_be_bytes().to_vec())
+                }
+            })
+            .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+        program.extend(encoded);
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::instruction::disassemble;
+
+    #[test]
+    fn assemble_instructions() {
+        let program = assemble(
+            "
+            CLS
+            LD V0, 0x0A
+            DRW V0 V1 5
+            LD I, #2F0
+            LD [I], V3 ; store V0 - V3
+            JP 0x200
+            ",
+        );
+
+        assert_eq!(
+            program,
+            Ok(vec![
+                0x00, 0xe0, 0x60, 0x0a, 0xd0, 0x15, 0xa2, 0xf0, 0xf3, 0x55, 0x12, 0x00
+            ])
+        );
+    }
+
+    #[test]
+    fn assemble_labels() {
+        let program = assemble(
+            "
+            start: LD V0, 1
+            loop:
+                ADD V0, 1
+                SE V0, 10
+                JP loop
+                CALL sprite
+                JP start
+            sprite:
+                DB 0xF0 0x90
+            ",
+        );
+
+        assert_eq!(
+            program,
+            Ok(vec![
+                0x60, 0x01, 0x70, 0x01, 0x30, 0x0a, 0x12, 0x02, 0x22, 0x0c, 0x12, 0x00, 0xf0, 0x90
+            ])
+        );
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert_eq!(
+            assemble("CLS\nJP nowhere"),
+            Err("line 2: unknown label: nowhere".to_string())
+        );
+        assert_eq!(
+            assemble("LD V0, 0x100"),
+            Err("line 1: 0x100 does not fit in a byte".to_string())
+        );
+        assert_eq!(
+            assemble("DRW V0, V1, 16"),
+            Err("line 1: 0x10 does not fit in a nibble".to_string())
+        );
+        assert!(assemble("MOV V0, V1").is_err());
+        assert!(assemble("a:\na: CLS").is_err());
+        assert_eq!(
+            assemble("CLS\nI: CLS"),
+            Err("line 2: reserved label name: I".to_string())
+        );
+        for name in ["dt", "VF", "K", "0x10"] {
+            assert!(assemble(&format!("{}: CLS", name)).is_err());
+        }
+        let source = format!("{}end: CLS", "DW 0\n".repeat(0x8000));
+        assert_eq!(
+            assemble(&source),
+            Err("line 32769: label end at 0x10200 is past the end of memory".to_string())
+        );
+    }
+
+    #[test]
+    fn assemble_disassemble_round_trip() {
+        let program = assemble(
+            "
+            HIGH
+            LD V1, 0
+            loop:
+                LD F, V1
+                DRW V1, V2, 5
+                LD B, V1
+                LD V3, [I]
+                LD V4, K
+                SHL V4, V4
+                SKNP V4
+                JP V0, loop
+                SCD 4
+                RND VF, 0xFF
+                DW 0xFFFF
+                LD I, LONG
+                DW 0x1234
+                PLANE 3
+                LD R, V7
+                LD V7, R
+                SYS 0x123
+                AUDIO
+                LD PITCH, VA
+            ",
+        )
+        .unwrap();
+
+        let listing = disassemble(&program);
+        assert_eq!(listing[1], "LD V1, 0x00");
+        assert_eq!(listing[9], "JP V0, 0x204");
+
+        assert_eq!(assemble(&listing.join("\n")), Ok(program));
+    }
+}
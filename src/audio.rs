@@ -0,0 +1,224 @@
+use std::f32::consts::TAU;
+use std::str::FromStr;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+use crate::frontend::Sound;
+
+pub const BEEP_FREQUENCY: f32 = 440.0;
+const SAMPLE_RATE: i32 = 44_100;
+const VOLUME: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Waveform, String> {
+        match name {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            _ => Err(format!("invalid waveform: {}", name)),
+        }
+    }
+}
+
+/** Value of the waveform in [-1, 1] at a phase in [0, 1) */
+fn wave(waveform: Waveform, phase: f32) -> f32 {
+    match waveform {
+        Waveform::Square => {
+            if phase < 0.5 {
+                1.0
+            } else {
+                -1.0
+            }
+        }
+        Waveform::Sine => (phase * TAU).sin(),
+        Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+    }
+}
+
+/** Beep generator run by the SDL audio callback */
+struct Tone {
+    waveform: Waveform,
+    frequency: f32,
+    sample_rate: f32,
+    // position in the current period, from 0 to 1
+    phase: f32,
+    volume: f32,
+    // XO-CHIP 128 bit pattern, played instead of the waveform, and its bits per second
+    pattern: Option<[u8; 16]>,
+    pattern_rate: f32,
+}
+
+impl Tone {
+    fn fill(&mut self, out: &mut [f32]) {
+        match self.pattern {
+            Some(pattern) => {
+                // a period is the whole pattern
+                let step = self.pattern_rate / 128.0 / self.sample_rate;
+                for sample in out.iter_mut() {
+                    let bit = (self.phase * 128.0) as usize % 128;
+                    let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                    *sample = if on { self.volume } else { -self.volume };
+                    self.phase = (self.phase + step) % 1.0;
+                }
+            }
+            None => {
+                let step = self.frequency / self.sample_rate;
+                for sample in out.iter_mut() {
+                    *sample = self.volume * wave(self.waveform, self.phase);
+                    self.phase = (self.phase + step) % 1.0;
+                }
+            }
+        }
+    }
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.fill(out);
+    }
+}
+
+pub struct Audio {
+    device: AudioDevice<Tone>,
+}
+
+impl Audio {
+    /** Opens the playback device, paused, with a 440Hz square wave */
+    pub fn new(audio_subsystem: &AudioSubsystem) -> Result<Audio, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired, |spec| Tone {
+            waveform: Waveform::Square,
+            frequency: BEEP_FREQUENCY,
+            sample_rate: spec.freq as f32,
+            phase: 0.0,
+            volume: VOLUME,
+            pattern: None,
+            pattern_rate: 0.0,
+        })?;
+        Ok(Audio { device })
+    }
+
+    pub fn set_frequency(&mut self, hz: f32) {
+        self.device.lock().frequency = hz;
+    }
+
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.device.lock().waveform = waveform;
+    }
+}
+
+impl Sound for Audio {
+    /** Starts or stops the beep */
+    fn play(&mut self, on: bool) {
+        if on {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: Option<[u8; 16]>, rate: f32) {
+        let mut tone = self.device.lock();
+        tone.pattern = pattern;
+        tone.pattern_rate = rate;
+        tone.phase = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn tone(waveform: Waveform, frequency: f32) -> Tone {
+        Tone {
+            waveform,
+            frequency,
+            sample_rate: 44_100.0,
+            phase: 0.0,
+            volume: 1.0,
+            pattern: None,
+            pattern_rate: 0.0,
+        }
+    }
+
+    #[test]
+    fn fill_square() {
+        // 4 samples per period
+        let mut tone = tone(Waveform::Square, 11_025.0);
+        let mut out = [0.0; 8];
+
+        tone.fill(&mut out);
+
+        assert_eq!(out, [1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn fill_triangle() {
+        let mut tone = tone(Waveform::Triangle, 11_025.0);
+        let mut out = [0.0; 5];
+
+        tone.fill(&mut out);
+
+        assert_eq!(out, [-1.0, 0.0, 1.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn fill_sine_continues_phase() {
+        let mut tone = tone(Waveform::Sine, 11_025.0);
+        let mut first = [0.0; 2];
+        let mut second = [0.0; 2];
+
+        tone.fill(&mut first);
+        tone.fill(&mut second);
+
+        let expected = [0.0, 1.0, 0.0, -1.0];
+        for (sample, expected) in first.iter().chain(second.iter()).zip(expected) {
+            assert!((sample - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn fill_pattern() {
+        let mut tone = tone(Waveform::Sine, 440.0);
+        let mut pattern = [0; 16];
+        pattern[0] = 0xf0;
+        pattern[15] = 0x01;
+        tone.pattern = Some(pattern);
+        // one bit per sample
+        tone.pattern_rate = 44_100.0;
+        let mut out = [0.0; 130];
+
+        tone.fill(&mut out);
+
+        assert_eq!(out[..8], [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+        assert!(out[8..127].iter().all(|&sample| sample == -1.0));
+        // the last bit, then the pattern loops
+        assert_eq!(out[127..], [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn parse_waveform() {
+        assert_eq!("sine".parse(), Ok(Waveform::Sine));
+        assert_eq!(
+            "saw".parse::<Waveform>(),
+            Err("invalid waveform: saw".to_string())
+        );
+    }
+}
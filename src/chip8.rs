@@ -1,11 +1,21 @@
+use std::collections::{BTreeSet, VecDeque};
 use std::fs;
-use std::path::PathBuf;
-
-use rand::Rng;
-
-use crate::io::IOContext;
-
-const CHIP8_FONT_SET: [u8; 80] = [
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{debug, error, info, trace, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::frontend::{Display, Input, Sound};
+use crate::instruction::{decode, Instruction};
+use crate::keypad::Keypad;
+
+// Address of the hex digit font, below the 0x200 program start
+pub const FONT_BASE: usize = 0x50;
+pub(crate) const CHIP8_FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -24,7 +34,69 @@ const CHIP8_FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
-#[derive(Debug, PartialEq)]
+// Address of the SCHIP 8x10 digit font, right after the hex font
+pub const BIGFONT_BASE: usize = FONT_BASE + CHIP8_FONT_SET.len();
+const SCHIP_BIGFONT_SET: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+/** Copies the small and big font sets to their place in memory */
+fn load_fonts(memory: &mut [u8]) {
+    memory[FONT_BASE..FONT_BASE + CHIP8_FONT_SET.len()].copy_from_slice(&CHIP8_FONT_SET);
+    memory[BIGFONT_BASE..BIGFONT_BASE + SCHIP_BIGFONT_SET.len()]
+        .copy_from_slice(&SCHIP_BIGFONT_SET);
+}
+
+// Memory size (bytes), XO-CHIP extends it to 64K
+const MEMORY_SIZE: usize = 4096;
+const XO_CHIP_MEMORY_SIZE: usize = 65536;
+
+// Where ROMs are loaded and start executing
+pub const LOAD_ADDRESS: usize = 0x200;
+
+// Instructions executed per 60Hz frame (~600Hz)
+pub const CYCLES_PER_FRAME: u32 = 10;
+// Speed multiplier while the turbo key is held
+pub const TURBO_FACTOR: u32 = 5;
+// COSMAC VIP machine cycles per 60Hz frame (1.7609 MHz clock, 8 clock periods per cycle)
+const VIP_CYCLES_PER_FRAME: i64 = 3668;
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// Frames run at once to catch up after a hitch, the time beyond is dropped
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+
+// XO-CHIP audio pattern bits played per second at the default pitch of 64
+const PATTERN_RATE: f32 = 4000.0;
+const DEFAULT_PITCH: u8 = 64;
+
+// Number of (pc, opcode) entries kept by the instruction trace
+const TRACE_LENGTH: usize = 256;
+
+// Number of key presses and releases queued for FX0A
+const KEY_EVENTS_LENGTH: usize = 16;
+
+// Number of instructions that can be undone
+const UNDO_LENGTH: usize = 64;
+
+// A rewind snapshot is taken every REWIND_INTERVAL frames, keeping 10 seconds of history
+const REWIND_INTERVAL: u32 = 6;
+const REWIND_LENGTH: usize = 100;
+
+// Display resolutions (pixels)
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ChipState {
     Block,
     Run,
@@ -32,1179 +104,5711 @@ pub enum ChipState {
     Clear,
     Pause,
     Quit,
+    StackOverflow,
+    StackUnderflow,
+    // Unknown opcode
+    Error(u16),
+}
+
+/** The machine state before and after an `emulate_cycle`, for front-ends reacting to
+ * draws, clears, key waits or exits */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub from: ChipState,
+    pub to: ChipState,
+}
+
+impl Transition {
+    pub fn changed(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+/** A scripted key press or release, applied once `cycle` instructions have been executed */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/** Fixed timestep of `Chip8::run_loop`: the real time elapsed is accumulated and consumed in
+ * 1/60s slices, each running a frame of instructions (`clock_hz / 60`) and a timer tick. The
+ * CPU rate, the timer rate and the frame rate no longer depend on how long an iteration of the
+ * loop takes */
+#[derive(Debug, Default)]
+struct FrameClock {
+    accumulated: Duration,
+}
+
+impl FrameClock {
+    /** Adds the real time elapsed since the last call and returns the number of frames due.
+     * When running late by more than MAX_CATCH_UP_FRAMES the missed time is dropped instead of
+     * being caught up in a long burst */
+    fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulated += elapsed;
+        let mut frames = 0;
+        while self.accumulated >= FRAME_DURATION {
+            if frames == MAX_CATCH_UP_FRAMES {
+                self.accumulated = Duration::ZERO;
+                break;
+            }
+            self.accumulated -= FRAME_DURATION;
+            frames += 1;
+        }
+        frames
+    }
+
+    /** The time left until the next frame is due */
+    fn until_next_frame(&self) -> Duration {
+        FRAME_DURATION - self.accumulated
+    }
+}
+
+/** DXYN counters since the machine was created, see `Chip8::draw_stats` */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DrawStats {
+    pub draws: u64,
+    // draws turning off a lit pixel (VF = 1)
+    pub collisions: u64,
+    // pixels turned on or off
+    pub pixels_flipped: u64,
+}
+
+/** Whether DXYN sprites crossing the right/bottom edges wrap around to the opposite side
+ * instead of being clipped. The starting coordinate always wraps */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DrawQuirk {
+    pub wrap_horizontal: bool,
+    pub wrap_vertical: bool,
+}
+
+/** The behaviors that differ between the CHIP-8 interpreters. The defaults are the modern
+ * behavior */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX (COSMAC VIP) instead of shifting VX in place
+    pub shift: bool,
+    // FX55/FX65 leave I pointing after the last register (COSMAC VIP)
+    pub load_store: bool,
+    // BXNN jumps to XNN plus VX (SCHIP) instead of NNN plus V0
+    pub jump: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0 (COSMAC VIP)
+    pub logic_vf_reset: bool,
+    // FX1E sets VF when I overflows past 0xFFF (Amiga), instead of leaving VF alone
+    pub i_overflow: bool,
+    // DXYN waits for the vertical blank (COSMAC VIP), at most one draw per frame
+    pub display_wait: bool,
+    // DXYN wraps sprites around the edges instead of clipping them
+    pub draw: DrawQuirk,
+}
+
+impl Quirks {
+    /** The quirks of a platform */
+    pub fn for_profile(profile: Profile) -> Quirks {
+        match profile {
+            Profile::CosmacVip => Quirks {
+                shift: true,
+                load_store: true,
+                logic_vf_reset: true,
+                display_wait: true,
+                ..Quirks::default()
+            },
+            Profile::SuperChip => Quirks {
+                jump: true,
+                ..Quirks::default()
+            },
+            Profile::XoChip => Quirks {
+                shift: true,
+                load_store: true,
+                draw: DrawQuirk {
+                    wrap_horizontal: true,
+                    wrap_vertical: true,
+                },
+                ..Quirks::default()
+            },
+            Profile::Modern => Quirks::default(),
+        }
+    }
+}
+
+/** The platforms whose behavior `Chip8::set_profile` reproduces */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    // The original interpreter of the COSMAC VIP
+    CosmacVip,
+    // SUPER-CHIP 1.1 on the HP-48
+    SuperChip,
+    // Octo's XO-CHIP
+    XoChip,
+    // What most ROMs written since the 90s expect
+    Modern,
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Profile, String> {
+        match name {
+            "vip" => Ok(Profile::CosmacVip),
+            "schip" => Ok(Profile::SuperChip),
+            "xochip" => Ok(Profile::XoChip),
+            "modern" => Ok(Profile::Modern),
+            _ => Err(format!("invalid profile: {}", name)),
+        }
+    }
+}
+
+/** A copy of the machine state, restored with `Chip8::load_state` */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+    memory: Vec<u8>,
+    v: [u8; 16],
+    i: usize,
+    program_counter: usize,
+    gfx: Vec<u8>,
+    width: usize,
+    height: usize,
+    selected_plane: u8,
+    delay_timer: u8,
+    sound_timer: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    stack: [u16; 16],
+    stack_pointer: usize,
+}
+
+/** What a single instruction can change, restored by `Chip8::undo_last`. Memory and the
+ * display are only kept for the instructions writing them */
+#[derive(Debug)]
+struct UndoEntry {
+    v: [u8; 16],
+    i: usize,
+    program_counter: usize,
+    stack: [u16; 16],
+    stack_pointer: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    flags: [u8; 8],
+    selected_plane: u8,
+    held_keys: Keypad,
+    state: ChipState,
+    // (address, previous value) of the bytes written by FX33/FX55
+    memory: Vec<(usize, u8)>,
+    // display and resolution before a draw, scroll, clear or resolution switch
+    gfx: Option<(Vec<u8>, usize, usize)>,
 }
 
 #[derive(Debug)]
 pub struct Chip8 {
-    // 4K memory
-    memory: [u8; 4096],
+    // 4K memory, 64K in XO-CHIP mode
+    memory: Vec<u8>,
+    // value of the memory not holding the fonts or the ROM
+    fill: u8,
     // general purpose registers named v0 - vf
     v: [u8; 16],
     // Index register
     i: usize,
     // value from 0x000 to 0xFFF
     program_counter: usize,
-    // screen with 2048 pixels (64 x 32)
-    pub gfx: [u8; 64 * 32],
+    // where the ROM is loaded, and the initial program counter
+    load_address: usize,
+    // the loaded ROM, kept for cold resets. load_address..load_address + rom.len() is the
+    // code region
+    rom: Vec<u8>,
+    // addresses in the code region written by FX33/FX55, only recorded while watching
+    code_watch: bool,
+    code_writes: Vec<usize>,
+    // FX33/FX55 writes below 0x200 (interpreter area and fonts) are dropped
+    protect_reserved: bool,
+    // (opcode, instruction) decoded at each address, empty while the cache is off
+    decode_cache: Vec<Option<(u16, Instruction)>>,
+    // screen with 2048 pixels (64 x 32), or 8192 pixels (128 x 64) in hires mode.
+    // Each pixel holds one bit per plane: bit 0 for plane 1, bit 1 for the XO-CHIP plane 2
+    pub gfx: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    // planes drawn, scrolled and cleared (bit mask set by FN01)
+    selected_plane: u8,
     pub state: ChipState,
     delay_timer: u8,
     sound_timer: u8,
+    // XO-CHIP sound: the 128 bit pattern played while the sound timer runs, and its pitch
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
     stack: [u16; 16],
     stack_pointer: usize,
+    // SCHIP RPL user flags (FX75/FX85), kept across runs with save_flags/load_flags
+    flags: [u8; 8],
     // keypad current state
-    pub keys: [u8; 16],
+    pub keys: Keypad,
+    // keys held down when FX0A started waiting
+    held_keys: Keypad,
+    // keys at the start of the frame being run, read by EX9E/EXA1
+    latched_keys: Option<Keypad>,
+    // scripted key events not applied yet, by cycle
+    replay: VecDeque<KeyEvent>,
+    // key presses and releases FX0A has not looked at yet, oldest first
+    key_events: VecDeque<KeyEvent>,
+    // instructions per second, spread over the 60Hz frames
+    clock_hz: u32,
+    // clock_hz / 60 remainder carried over to the next frame
+    clock_remainder: u32,
+    // run turbo_factor times faster while turbo is on
+    turbo: bool,
+    turbo_factor: u32,
+    // instructions executed so far
+    cycles: u64,
+    // pace frames by the COSMAC VIP cost of each instruction instead of clock_hz
+    vip_timing: bool,
+    // COSMAC VIP machine cycles of the instructions executed so far
+    machine_cycles: u64,
+    // machine cycles left in the frame, negative when the last instruction overran it
+    machine_cycle_budget: i64,
+    last_op_code: u16,
+    // opcode classes executed so far, e.g. "8XY4"
+    coverage: BTreeSet<&'static str>,
+    // recently executed (pc, opcode), only recorded while tracing
+    trace_enabled: bool,
+    trace: Vec<(usize, u16)>,
+    // state before the recently executed instructions, only recorded while undo is enabled
+    undo_enabled: bool,
+    undo: VecDeque<UndoEntry>,
+    quirks: Quirks,
+    // a DXYN is waiting for the next timer tick
+    vblank_wait: bool,
+    // gfx changed since it was last presented
+    dirty: bool,
+    // pause after this many cycles without progress, None when the watchdog is off
+    watchdog: Option<u64>,
+    // PC addresses pausing the machine before their instruction runs
+    breakpoints: BTreeSet<usize>,
+    // the breakpoint the machine paused at, its instruction runs on resume
+    breakpoint_hit: Option<usize>,
+    // memory addresses pausing the machine when an instruction writes them
+    watchpoints: BTreeSet<usize>,
+    // (address, PC) of the last write to a watchpoint
+    watchpoint_hit: Option<(usize, usize)>,
+    // an unknown opcode pauses instead of halting with ChipState::Error
+    pause_on_unknown_opcode: bool,
+    // (PC, opcode) of the unknown opcode the machine paused at
+    unknown_opcode: Option<(usize, u16)>,
+    // V, I and SP, and the cycle count, when progress was last seen
+    watchdog_state: ([u8; 16], usize, usize),
+    watchdog_start: u64,
+    draw_stats: DrawStats,
+    // gfx indices turned off by DXYN this frame, only recorded in collision debug mode
+    collision_debug: bool,
+    collided: Vec<usize>,
+    // periodic (frame, snapshot), oldest first
+    rewind: VecDeque<(u64, SaveState)>,
+    rewind_interval: u32,
+    frames_since_snapshot: u32,
+    // frames counted by record_rewind
+    frame_count: u64,
+    // CXNN random numbers
+    rng: StdRng,
+    // run_loop runs at full speed without sound (see `deterministic`)
+    deterministic: bool,
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
-        // Clear memory
-        let mut memory = [0; 4096];
+        Chip8::with_quirks(Quirks::default())
+    }
 
-        // Load font set
-        for number in 0..80 {
-            memory[number] = CHIP8_FONT_SET[number];
-        }
+    /** A machine with the given quirks instead of the modern behavior */
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
+        // Clear memory
+        let mut memory = vec![0; MEMORY_SIZE];
+        load_fonts(&mut memory);
 
         Chip8 {
             memory,
+            fill: 0,
             v: [0; 16],
             i: 0,
-            program_counter: 0x200,
-            gfx: [0; 64 * 32],
+            program_counter: LOAD_ADDRESS,
+            load_address: LOAD_ADDRESS,
+            rom: Vec::new(),
+            code_watch: false,
+            code_writes: Vec::new(),
+            protect_reserved: false,
+            decode_cache: Vec::new(),
+            gfx: vec![0; LORES_WIDTH * LORES_HEIGHT],
+            width: LORES_WIDTH,
+            height: LORES_HEIGHT,
+            selected_plane: 1,
             state: ChipState::Run,
             delay_timer: 0,
             sound_timer: 0,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
             stack: [0; 16],
             stack_pointer: 0,
-            keys: [0; 16],
+            flags: [0; 8],
+            keys: Keypad::default(),
+            held_keys: Keypad::default(),
+            latched_keys: None,
+            replay: VecDeque::new(),
+            key_events: VecDeque::new(),
+            clock_hz: CYCLES_PER_FRAME * 60,
+            clock_remainder: 0,
+            turbo: false,
+            turbo_factor: TURBO_FACTOR,
+            cycles: 0,
+            vip_timing: false,
+            machine_cycles: 0,
+            machine_cycle_budget: 0,
+            last_op_code: 0,
+            coverage: BTreeSet::new(),
+            trace_enabled: false,
+            trace: Vec::new(),
+            undo_enabled: false,
+            undo: VecDeque::new(),
+            quirks,
+            vblank_wait: false,
+            dirty: false,
+            watchdog: None,
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            watchpoints: BTreeSet::new(),
+            watchpoint_hit: None,
+            pause_on_unknown_opcode: false,
+            unknown_opcode: None,
+            watchdog_state: ([0; 16], 0, 0),
+            watchdog_start: 0,
+            draw_stats: DrawStats::default(),
+            collision_debug: false,
+            collided: Vec::new(),
+            rewind: VecDeque::with_capacity(REWIND_LENGTH),
+            rewind_interval: REWIND_INTERVAL,
+            frames_since_snapshot: 0,
+            frame_count: 0,
+            rng: StdRng::from_entropy(),
+            deterministic: false,
         }
     }
 
-    pub fn load_game(&mut self, game_file_path: PathBuf) {
-        let contents = fs::read(game_file_path).unwrap();
-        let mut index = self.program_counter;
-        for val in contents {
-            self.memory[index] = u8::try_from(val).unwrap();
-            index += 1;
+    /** A machine loading ROMs and starting at `load_address` instead of 0x200 (0x600 on the ETI-660) */
+    pub fn new_with_entry(load_address: usize) -> Chip8 {
+        Chip8 {
+            program_counter: load_address,
+            load_address,
+            ..Chip8::new()
         }
     }
 
-    pub fn run_loop(&mut self, io_context: &mut IOContext) -> Result<(), String> {
-        'running: loop {
-            if self.state != ChipState::Pause {
-                self.emulate_cycle();
-            }
-
-            io_context
-                .keyboard
-                .keys_pressed(&mut self.keys, &mut self.state);
+    /** A machine whose CXNN random numbers are reproducible */
+    pub fn with_seed(seed: u64) -> Chip8 {
+        Chip8 {
+            rng: StdRng::seed_from_u64(seed),
+            ..Chip8::new()
+        }
+    }
 
-            match self.state {
-                ChipState::Draw => io_context.renderer.draw(self.gfx)?,
-                ChipState::Clear => io_context.renderer.clear(),
-                ChipState::Quit => break 'running,
-                _ => {}
-            }
+    /** A machine for fuzzing and replays: seeded like `with_seed`, and its `run_loop` neither
+     * sleeps between frames nor plays sound, so runs only depend on the ROM and the input */
+    pub fn deterministic(seed: u64) -> Chip8 {
+        Chip8 {
+            deterministic: true,
+            ..Chip8::with_seed(seed)
         }
+    }
 
-        Ok(())
+    /** Sets the number of instructions executed per 60Hz frame, a clock past u32::MAX is
+     * capped */
+    pub fn set_cycles_per_frame(&mut self, cycles_per_frame: u32) {
+        self.clock_hz = cycles_per_frame.saturating_mul(60);
     }
 
-    pub fn emulate_cycle(&mut self) {
-        if self.state != ChipState::Block {
-            self.state = ChipState::Run;
-        }
+    /** Sets the number of instructions executed per second. The timers still tick at 60Hz,
+     * once per frame, so a clock that is not a multiple of 60 alternates between frames of
+     * `hz / 60` and `hz / 60 + 1` instructions */
+    pub fn set_clock_hz(&mut self, hz: u32) {
+        self.clock_hz = hz;
+        self.clock_remainder = 0;
+    }
 
-        self.execute();
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
 
-        if self.state == ChipState::Block {
-            return;
-        }
+    pub fn set_turbo_factor(&mut self, turbo_factor: u32) {
+        self.turbo_factor = turbo_factor;
+    }
 
-        // Update timers
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
-            ::std::thread::sleep(std::time::Duration::new(0, 1_000_000_000u32 / 60));
-        }
-        if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                println!("BEEP!")
-            }
-            self.sound_timer -= 1;
+    /** Number of instructions to execute in the next frame */
+    fn frame_cycles(&mut self) -> u32 {
+        let cycles = self.clock_remainder.saturating_add(self.clock_hz);
+        self.clock_remainder = cycles % 60;
+        if self.turbo {
+            (cycles / 60).saturating_mul(self.turbo_factor)
+        } else {
+            cycles / 60
         }
     }
 
-    fn get_op_code(&self) -> u16 {
-        u16::try_from(self.memory[self.program_counter]).unwrap() << 8
-            | u16::try_from(self.memory[self.program_counter + 1]).unwrap()
+    /** Runs as many instructions per frame as the COSMAC VIP would have, from the cost of
+     * each instruction (see `cycle_cost`), instead of a fixed number */
+    pub fn set_vip_timing(&mut self, vip_timing: bool) {
+        self.vip_timing = vip_timing;
+        self.machine_cycle_budget = 0;
     }
 
-    fn execute(&mut self) {
-        // Fetch Opcode
-        let op_code = self.get_op_code();
-
-        // Decode Opcode
-        match op_code & 0xF000 {
-            0x0000 => match op_code & 0x000F {
-                // 0x00E0
-                0x0000 => self.clear_screen(),
-                // 0x00EE
-                0x000E => self.return_from_subroutine(),
-                _ => panic!("Unknown opcode [0x0000]: {:#06x}", op_code),
-            },
-            // 0x1NNN: goto NNN
-            0x1000 => self.goto(op_code),
-            // 2NNN
-            0x2000 => self.call_subroutine(op_code),
-            // 3XNN
-            0x3000 => self.skip_if_eq_to_nn(op_code),
-            // 4XNN
-            0x4000 => self.skip_if_not_eq_to_nn(op_code),
-            // 5XY0
-            0x5000 => self.skip_if_vx_eq_to_vy(op_code),
-            // 6XNN
-            0x6000 => self.set_vx_to_nn(op_code),
-            // 7XNN
-            0x7000 => self.add_nn_to_vx(op_code),
-
-            0x8000 => match op_code & 0x000F {
-                // 8XY0
-                0x0000 => self.set_vx_to_vy(op_code),
-                // 8XY1
-                0x0001 => self.set_vx_to_vx_or_vy(op_code),
-                // 8XY2
-                0x0002 => self.set_vx_to_vx_and_vy(op_code),
-                // 8XY3
-                0x0003 => self.set_vx_to_vx_xor_vy(op_code),
-                // 8XY4
-                0x0004 => self.set_vx_to_vx_plus_vy(op_code),
-                // 8XY5
-                0x0005 => self.set_vx_to_vx_minus_vy(op_code),
-                // 8XY6
-                0x0006 => self.shift_right(op_code),
-                // 8XY7
-                0x0007 => self.set_vx_to_vy_minus_vx(op_code),
-                // 8XYE
-                0x000E => self.shift_left(op_code),
-                _ => panic!("Unknown opcode [0x8000]: {:#06x}", op_code),
-            },
-            // 9XY0
-            0x9000 => self.skip_if_vx_not_eq_vy(op_code),
-            // ANNN
-            0xA000 => self.set_i_to_nnn(op_code),
-            // BNNN
-            0xB000 => self.goto_nnn_plus_v0(op_code),
-            // CXNN
-            0xC000 => self.set_vx_to_rand_and_nn(op_code),
-            // DXYN
-            0xD000 => self.draw(op_code),
-
-            0xE000 => match op_code & 0x000F {
-                // EX9E
-                0x000E => self.skip_if_key_pressed(op_code),
-                // EXA1
-                0x0001 => self.skip_if_not_key_pressed(op_code),
-                _ => panic!("Unknown opcode [0xE000]: {:#06x}", op_code),
-            },
+    /** COSMAC VIP machine cycles the executed instructions would have taken */
+    pub fn machine_cycle_count(&self) -> u64 {
+        self.machine_cycles
+    }
 
-            0xF000 => match op_code & 0x00FF {
-                // FX07
-                0x0007 => self.set_vx_to_delay_timer(op_code),
-                // FX0A
-                0x000A => self.is_key_press(op_code),
-                // FX15
-                0x0015 => self.set_delay_timer_to_vx(op_code),
-                // FX18
-                0x0018 => self.set_sound_timer_to_vx(op_code),
-                // FX1E
-                0x001E => self.add_vx_to_i(op_code),
-                // FX29
-                0x0029 => self.set_i_to_sprite(op_code),
-                // FX33
-                0x0033 => self.bcd(op_code),
-                // FX55
-                0x0055 => self.reg_dump(op_code),
-                // FX65
-                0x0065 => self.reg_load(op_code),
-                _ => panic!("Unknown opcode [0xF000]: {:#06x}", op_code),
-            },
-            _ => panic!("Unknown opcode: {}", op_code),
-        }
+    /** Number of instructions executed since power on */
+    pub fn cycle_count(&self) -> u64 {
+        self.cycles
     }
-    /** OP Codes  
-     * Reference: https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
-     */
 
-    /** 0x00E0: Clears the screen */
-    fn clear_screen(&mut self) {
-        self.gfx.fill(0);
-        self.state = ChipState::Clear;
-        self.program_counter += 2;
+    /** The most recently executed opcode */
+    pub fn last_opcode(&self) -> u16 {
+        self.last_op_code
     }
 
-    /** 0x00EE: Returns from subroutine */
-    fn return_from_subroutine(&mut self) {
-        self.stack_pointer -= 1;
-        self.program_counter =
-            usize::try_from(self.stack[self.stack_pointer] & 0x0FFF).unwrap() + 2;
+    /** The opcode classes (see `Instruction::pattern`) executed so far, to check which
+     * instructions a test ROM exercises */
+    pub fn opcode_coverage(&self) -> &BTreeSet<&'static str> {
+        &self.coverage
     }
 
-    /** 0x1NNN: goto NNN */
-    fn goto(&mut self, op_code: u16) {
-        self.program_counter = usize::try_from(op_code & 0x0FFF).unwrap();
+    /** Starts or stops recording executed instructions */
+    pub fn set_trace_enabled(&mut self, enabled: bool) {
+        if enabled && self.trace.capacity() == 0 {
+            // The trace is trimmed when it reaches twice its length, so it never reallocates
+            self.trace.reserve_exact(2 * TRACE_LENGTH);
+        }
+        self.trace_enabled = enabled;
     }
 
-    /** 2NNN: Calls subroutine at NNN */
-    fn call_subroutine(&mut self, op_code: u16) {
-        self.stack[self.stack_pointer] = u16::try_from(self.program_counter).unwrap();
-        self.stack_pointer += 1;
-        self.program_counter = usize::try_from(op_code & 0x0FFF).unwrap();
+    /** The last executed (pc, opcode) pairs, oldest first */
+    pub fn recent_trace(&self) -> &[(usize, u16)] {
+        &self.trace[self.trace.len().saturating_sub(TRACE_LENGTH)..]
     }
 
-    /** 3XNN: Skips the next instruction if VX equals NN */
-    fn skip_if_eq_to_nn(&mut self, op_code: u16) {
-        let x = (usize::try_from(op_code).unwrap() & 0x0F00) >> 8;
-        let val = u8::try_from(op_code & 0x00FF).unwrap();
-        if self.v[x] == val {
-            self.program_counter += 4;
+    /** Starts or stops recording the FX33/FX55 writes into the loaded ROM, to debug
+     * self-modifying code */
+    pub fn set_code_watch(&mut self, enabled: bool) {
+        self.code_watch = enabled;
+    }
+
+    /** Makes the instruction writes below 0x200 no-ops, so that a stray FX55 cannot corrupt
+     * the fonts */
+    pub fn set_protect_reserved(&mut self, protect_reserved: bool) {
+        self.protect_reserved = protect_reserved;
+    }
+
+    /** Keeps the decoded instructions by address so that loops are not decoded again, an
+     * instruction is dropped from the cache when its memory is written */
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = if enabled {
+            vec![None; self.memory.len()]
         } else {
-            self.program_counter += 2;
+            Vec::new()
+        };
+    }
+
+    /** Drops the whole decode cache after memory was replaced */
+    fn clear_decode_cache(&mut self) {
+        if !self.decode_cache.is_empty() {
+            self.set_decode_cache(true);
         }
     }
 
-    /** 4XNN: Skips the next instruction if VX does not equal NN */
-    fn skip_if_not_eq_to_nn(&mut self, op_code: u16) {
-        let x = (usize::try_from(op_code).unwrap() & 0x0F00) >> 8;
-        let val = u8::try_from(op_code & 0x00FF).unwrap();
-        if self.v[x] != val {
-            self.program_counter += 4;
-        } else {
-            self.program_counter += 2;
+    /** Drops the cached instructions reading `address`, the one starting there and the one
+     * starting right before */
+    fn invalidate_decode_cache(&mut self, address: usize) {
+        let len = self.decode_cache.len();
+        if len != 0 {
+            self.decode_cache[address] = None;
+            self.decode_cache[(address + len - 1) % len] = None;
         }
     }
 
-    /** 5XY0: Skips the next instruction if VX equals VY */
-    fn skip_if_vx_eq_to_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        if self.v[x] == self.v[y] {
-            self.program_counter += 4;
-        } else {
-            self.program_counter += 2;
+    /** Addresses of the loaded ROM written while the code watch was on, in write order */
+    pub fn code_writes(&self) -> &[usize] {
+        &self.code_writes
+    }
+
+    /** Starts or stops recording what each instruction changes, for `undo_last` */
+    pub fn set_undo_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.undo.clear();
         }
+        self.undo_enabled = enabled;
     }
 
-    /** 6XNN: Sets VX to NN */
-    fn set_vx_to_nn(&mut self, op_code: u16) {
-        let x = (usize::try_from(op_code).unwrap() & 0x0F00) >> 8;
-        let val = u8::try_from(op_code & 0x00FF).unwrap();
-        self.v[x] = val;
-        self.program_counter += 2;
+    /** Reverts the last executed instruction, up to UNDO_LENGTH instructions back. Returns
+     * false when there is nothing left to undo. CXNN random numbers are not rolled back */
+    pub fn undo_last(&mut self) -> bool {
+        let Some(entry) = self.undo.pop_back() else {
+            return false;
+        };
+        self.v = entry.v;
+        self.i = entry.i;
+        self.program_counter = entry.program_counter;
+        self.stack = entry.stack;
+        self.stack_pointer = entry.stack_pointer;
+        self.delay_timer = entry.delay_timer;
+        self.sound_timer = entry.sound_timer;
+        self.audio_pattern = entry.audio_pattern;
+        self.pitch = entry.pitch;
+        self.flags = entry.flags;
+        self.selected_plane = entry.selected_plane;
+        self.held_keys = entry.held_keys;
+        self.state = entry.state;
+        for (address, value) in entry.memory {
+            self.memory[address] = value;
+            self.invalidate_decode_cache(address);
+        }
+        if let Some((gfx, width, height)) = entry.gfx {
+            (self.gfx, self.width, self.height) = (gfx, width, height);
+            self.dirty = true;
+        }
+        self.vblank_wait = false;
+        self.cycles -= 1;
+        true
     }
 
-    /** 7XNN: Adds NN to VX */
-    fn add_nn_to_vx(&mut self, op_code: u16) {
-        let x = (usize::try_from(op_code).unwrap() & 0x0F00) >> 8;
-        let val = u8::try_from(op_code & 0x00FF).unwrap();
-        self.v[x] += val;
-        self.program_counter += 2;
+    /** Records the state `instruction` is about to change */
+    fn record_undo(&mut self, instruction: Instruction) {
+        let written = match instruction {
+            Instruction::Bcd { .. } => 3,
+            Instruction::RegDump { x } => x + 1,
+            _ => 0,
+        };
+        let memory = (0..written)
+            .map(|n| {
+                let address = self.address(n);
+                (address, self.memory[address])
+            })
+            .collect();
+        let gfx = match instruction {
+            Instruction::ScrollDown(_)
+            | Instruction::ClearScreen
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Lores
+            | Instruction::Hires
+            | Instruction::Draw { .. } => Some((self.gfx.clone(), self.width, self.height)),
+            _ => None,
+        };
+
+        if self.undo.len() == UNDO_LENGTH {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(UndoEntry {
+            v: self.v,
+            i: self.i,
+            program_counter: self.program_counter,
+            stack: self.stack,
+            stack_pointer: self.stack_pointer,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            flags: self.flags,
+            selected_plane: self.selected_plane,
+            held_keys: self.held_keys,
+            state: self.state,
+            memory,
+            gfx,
+        });
     }
 
-    /** 8XY0: Sets VX to the value of VY */
-    fn set_vx_to_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        self.v[x] = self.v[y];
-        self.program_counter += 2;
+    /** Fills the memory with `byte` instead of zeroes, apart from the fonts and the loaded
+     * ROM, so reads of memory the ROM never wrote stand out in dumps. Memory added by XO-CHIP
+     * and cleared by a cold reset gets the same byte. Meant to be called before running, what
+     * the ROM wrote is overwritten */
+    pub fn set_fill(&mut self, byte: u8) {
+        self.fill = byte;
+        self.memory.fill(byte);
+        load_fonts(&mut self.memory);
+        self.copy_rom();
+        self.clear_decode_cache();
     }
 
-    /** 8XY1: Sets VX to VX or VY (bitwise OR operation) */
-    fn set_vx_to_vx_or_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        self.v[x] |= self.v[y];
-        self.program_counter += 2;
+    /** Copies the loaded ROM at the load address again. A ROM loaded in XO-CHIP mode can be
+     * larger than the 4K left after `set_xo_chip(false)`, its end is then dropped */
+    fn copy_rom(&mut self) {
+        let start = self.load_address.min(self.memory.len());
+        let end = (self.load_address + self.rom.len()).min(self.memory.len());
+        self.memory[start..end].copy_from_slice(&self.rom[..end - start]);
     }
 
-    /** 8XY2: Sets VX to VX and VY (bitwise AND operation)*/
-    fn set_vx_to_vx_and_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        self.v[x] &= self.v[y];
-        self.program_counter += 2;
+    /** Switches between 4K of memory and the 64K of XO-CHIP, which also enables F000 NNNN.
+     * Memory past 4K is cleared when switching back */
+    pub fn set_xo_chip(&mut self, xo_chip: bool) {
+        let size = if xo_chip {
+            XO_CHIP_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        };
+        self.memory.resize(size, self.fill);
+        self.i %= size;
+        self.clear_decode_cache();
     }
 
-    /** 8XY3: Sets VX to VX xor VY */
-    fn set_vx_to_vx_xor_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        self.v[x] ^= self.v[y];
-        self.program_counter += 2;
+    /** Sets all the quirks, and the XO-CHIP memory, to the behavior of a platform */
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.quirks = Quirks::for_profile(profile);
+        self.set_xo_chip(profile == Profile::XoChip);
     }
 
-    /** 8XY4: Adds VY to VX. VF is set to 1 when there's an overflow, and to 0 when there is not. */
-    fn set_vx_to_vx_plus_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        self.v[0xF] = if self.v[y] > self.v[x] { 1 } else { 0 };
-        self.v[x] += self.v[y];
-        self.program_counter += 2;
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
     }
 
-    /** 8XY5: VY is subtracted from VX. VF is set to 0 when there's an underflow, and 1 when there is not. (i.e. VF set to 1 if VX >= VY and 0 if not) */
-    fn set_vx_to_vx_minus_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        self.v[0xF] = if self.v[x] > self.v[y] { 1 } else { 0 };
-        self.v[x] -= self.v[y];
-        self.program_counter += 2;
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
-    /** 8XY6: Stores the least significant bit of VX in VF and then shifts VX to the right by 1 */
-    fn shift_right(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.v[0xF] = self.v[x] & 0x01;
-        self.v[x] >>= 1;
-        self.program_counter += 2;
+    pub fn set_shift_quirk(&mut self, shift_quirk: bool) {
+        self.quirks.shift = shift_quirk;
     }
 
-    /** 8XY7: Sets VX to VY minus VX. VF is set to 0 when there's an underflow, and 1 when there is not. (i.e. VF set to 1 if VY >= VX) */
-    fn set_vx_to_vy_minus_vx(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        self.v[0xF] = if self.v[y] > self.v[x] { 1 } else { 0 };
-        self.v[x] = self.v[y] - self.v[x];
-        self.program_counter += 2;
+    pub fn set_load_store_quirk(&mut self, load_store_quirk: bool) {
+        self.quirks.load_store = load_store_quirk;
     }
 
-    /** 8XYE: Stores the most significant bit of VX in VF and then shifts VX to the left by 1 */
-    fn shift_left(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.v[0xF] = self.v[x] >> 7;
-        self.v[x] <<= 1;
-        self.program_counter += 2;
+    pub fn set_jump_quirk(&mut self, jump_quirk: bool) {
+        self.quirks.jump = jump_quirk;
     }
 
-    /** 9XY0: Skips the next instruction if VX does not equal VY */
-    fn skip_if_vx_not_eq_vy(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let y = (op_code & 0x00F0) >> 4;
-        if self.v[x] != self.v[y] {
-            self.program_counter += 4;
+    pub fn set_logic_vf_reset_quirk(&mut self, logic_vf_reset_quirk: bool) {
+        self.quirks.logic_vf_reset = logic_vf_reset_quirk;
+    }
+
+    pub fn set_i_overflow_quirk(&mut self, i_overflow_quirk: bool) {
+        self.quirks.i_overflow = i_overflow_quirk;
+    }
+
+    pub fn set_draw_quirk(&mut self, draw_quirk: DrawQuirk) {
+        self.quirks.draw = draw_quirk;
+    }
+
+    pub fn set_display_wait_quirk(&mut self, display_wait_quirk: bool) {
+        self.quirks.display_wait = display_wait_quirk;
+    }
+
+    /** Switches between the 64x32 and the SCHIP 128x64 display, clearing the screen */
+    pub fn set_hires(&mut self, hires: bool) {
+        (self.width, self.height) = if hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
         } else {
-            self.program_counter += 2;
-        }
+            (LORES_WIDTH, LORES_HEIGHT)
+        };
+        self.gfx = vec![0; self.width * self.height];
     }
 
-    /** ANNN: Sets I to the address NNN */
-    fn set_i_to_nnn(&mut self, op_code: u16) {
-        self.i = usize::try_from(op_code & 0x0FFF).unwrap();
-        self.program_counter += 2;
+    /** Returns whether the display changed (drawn, scrolled or cleared) since the last call,
+     * which clears the flag */
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
     }
 
-    /** BNNN: Jumps to the address NNN plus V0 */
-    fn goto_nnn_plus_v0(&mut self, op_code: u16) {
-        let val = u16::try_from(op_code & 0x0FFF).unwrap();
-        self.program_counter = usize::try_from(self.v[0]).unwrap() + usize::try_from(val).unwrap();
+    /** Whether the last DXYN turned off a lit pixel (VF) */
+    pub fn collision_occurred(&self) -> bool {
+        self.v[0xF] == 1
     }
 
-    /** CXNN: Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN */
-    fn set_vx_to_rand_and_nn(&mut self, op_code: u16) {
-        let val = u8::try_from(op_code & 0x00FF).unwrap();
-        let x = usize::try_from((op_code & 0x0F00) >> 8).unwrap();
+    /** Records the pixels DXYN turns off every frame, for the front-end to highlight them */
+    pub fn set_collision_debug(&mut self, enabled: bool) {
+        self.collision_debug = enabled;
+        self.collided.clear();
+    }
 
-        self.v[x] = rand::thread_rng().gen_range(0..255) & val;
-        self.program_counter += 2;
+    /** The gfx indices turned off by DXYN during the last frame, empty unless collision
+     * debug is on */
+    pub fn collided_pixels(&self) -> &[usize] {
+        &self.collided
     }
 
-    /** DXYN: Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height of N pixels. */
-    fn draw(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = self.v[(op_code & 0x0F00) >> 8];
-        let y = self.v[(op_code & 0x00F0) >> 4];
-        let height = op_code & 0x000F;
-        self.v[0x0F] = 0;
+    /** Whether the beep should sound, while the sound timer runs */
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
 
-        for y_offset in 0..height {
-            if height == 0 {
-                break;
-            }
-            let pixel = self.memory[self.i + y_offset];
-            for x_offset in 0..8 {
-                if (pixel & (0x80 >> u8::try_from(x_offset).unwrap())) != 0 {
-                    let x = usize::try_from(x).unwrap();
-                    let y = usize::try_from(y).unwrap();
-                    let index = x + x_offset + ((y + y_offset) * 64);
-                    if self.gfx[index] == 1 {
-                        self.v[0x0F] = 1;
-                    }
-                    self.gfx[index] ^= 1;
-                }
-            }
-        }
+    /** The XO-CHIP audio pattern loaded by F002, None until a ROM loads one */
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
 
-        self.state = ChipState::Draw;
+    /** The pattern bits played per second at the FX3A pitch:
+     * 4000 * 2 ^ ((pitch - 64) / 48) */
+    pub fn pattern_rate(&self) -> f32 {
+        PATTERN_RATE * 2f32.powf((f32::from(self.pitch) - f32::from(DEFAULT_PITCH)) / 48.0)
+    }
 
-        self.program_counter += 2;
+    /** Draws, collisions and flipped pixels counted so far, to analyze sprite flicker */
+    pub fn draw_stats(&self) -> DrawStats {
+        self.draw_stats
     }
 
-    /** EX9E: Skips the next instruction if the key stored in VX is pressed */
-    fn skip_if_key_pressed(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let index = usize::try_from(self.v[x]).unwrap();
-        if self.keys[index] != 0 {
-            self.program_counter += 4;
-        } else {
-            self.program_counter += 2;
-        }
+    /** The whole memory (4K, or 64K in XO-CHIP mode), for inspecting what a ROM wrote */
+    pub fn dump_memory(&self) -> &[u8] {
+        &self.memory
     }
 
-    /** EXA1: Skips the next instruction if the key stored in VX is not pressed */
-    fn skip_if_not_key_pressed(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        let index = usize::try_from(self.v[x]).unwrap();
-        if self.keys[index] == 0 {
-            self.program_counter += 4;
-        } else {
-            self.program_counter += 2;
+    /** The byte of memory at `address`, None past the end of memory */
+    pub fn peek(&self, address: usize) -> Option<u8> {
+        self.memory.get(address).copied()
+    }
+
+    /** Patches a byte of memory from a debugger or a tool. Unlike the writes of instructions
+     * it ignores the reserved memory protection and the watchpoints */
+    pub fn poke(&mut self, address: usize, value: u8) -> Result<(), String> {
+        if address >= self.memory.len() {
+            return Err(format!(
+                "address {:#06x} is past the end of memory ({:#06x})",
+                address,
+                self.memory.len()
+            ));
         }
+        self.memory[address] = value;
+        self.invalidate_decode_cache(address);
+        Ok(())
     }
 
-    /** FX07: Sets VX to the value of the delay timer */
-    fn set_vx_to_delay_timer(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.v[x] = self.delay_timer;
-        self.program_counter += 2;
+    /** Writes the memory image to a file */
+    pub fn dump_memory_to_file(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, &self.memory)
+            .map_err(|e| format!("could not write memory dump {}: {}", path.display(), e))
     }
 
-    /** Helper for FX0A  */
-    fn is_key_press(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.state = ChipState::Block;
-        for i in 0..16 {
-            if self.keys[i] == 1 {
-                println!("FX0A key {} was pressed", i);
-                self.v[x] = u8::try_from(i).unwrap();
-                self.state = ChipState::Run;
-                break;
+    /** Restores the RPL user flags saved by a previous run, a missing file leaves them cleared */
+    pub fn load_flags(&mut self, path: &Path) -> Result<(), String> {
+        match fs::read(path) {
+            Ok(bytes) => {
+                let len = bytes.len().min(self.flags.len());
+                self.flags[..len].copy_from_slice(&bytes[..len]);
+                Ok(())
             }
-        }
-
-        if self.state == ChipState::Run {
-            self.program_counter += 2;
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("could not read flags {}: {}", path.display(), e)),
         }
     }
 
-    /** FX15: Sets the delay timer to VX */
-    fn set_delay_timer_to_vx(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.delay_timer = self.v[x];
-        self.program_counter += 2;
+    /** Writes the RPL user flags, so that they survive across runs like on the HP-48 */
+    pub fn save_flags(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.flags)
+            .map_err(|e| format!("could not write flags {}: {}", path.display(), e))
     }
 
-    /**  FX18: Sets the sound timer to VX */
-    fn set_sound_timer_to_vx(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.sound_timer = self.v[x];
-        self.program_counter += 2;
+    /** The display, one byte per pixel row by row (see `gfx`) */
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.gfx
     }
 
-    /** FX1E: Adds VX to I. VF is not affected */
-    fn add_vx_to_i(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.i += usize::try_from(self.v[x]).unwrap();
-        self.program_counter += 2;
+    pub fn press_key(&mut self, key: u8) {
+        self.set_key(key, true);
     }
 
-    /** FX29: Sets I to the location of the sprite for the character in VX */
-    fn set_i_to_sprite(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        self.i = usize::try_from(self.v[x]).unwrap() * 0x5;
-        self.program_counter += 2;
+    pub fn release_key(&mut self, key: u8) {
+        self.set_key(key, false);
     }
 
-    /** FX33: Stores the binary-coded decimal representation of VX, with the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2 */
-    fn bcd(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        self.memory[self.i] = self.v[(op_code & 0x0F00) >> 8] / 100;
-        self.memory[self.i + 1] = (self.v[(op_code & 0x0F00) >> 8] / 10) % 10;
-        self.memory[self.i + 2] = (self.v[(op_code & 0x0F00) >> 8] % 100) % 10;
-        self.program_counter += 2;
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        if key < 16 && self.keys.is_pressed(key) != pressed {
+            self.queue_key_event(key, pressed);
+        }
+        self.keys.set(key, pressed);
     }
 
-    /**  FX55: Stores from V0 to VX (including VX) in memory, starting at address I. The offset from I is increased by 1 for each value written, but I itself is left unmodified */
-    fn reg_dump(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        for n in 0..(x + 1) {
-            self.memory[self.i + n] = self.v[n]
+    /** Queues the keys that changed from `before` to the current keypad */
+    fn queue_key_changes(&mut self, before: Keypad) {
+        for key in 0..16 {
+            let pressed = self.keys.is_pressed(key);
+            if before.is_pressed(key) != pressed {
+                self.queue_key_event(key, pressed);
+            }
         }
-        self.program_counter += 2;
     }
 
-    /** FX65: Fills from V0 to VX (including VX) with values from memory, starting at address I. The offset from I is increased by 1 for each value read, but I itself is left unmodified */
-    fn reg_load(&mut self, op_code: u16) {
-        let op_code = usize::try_from(op_code).unwrap();
-        let x = (op_code & 0x0F00) >> 8;
-        for n in 0..(x + 1) {
-            self.v[n] = self.memory[self.i + n]
+    fn queue_key_event(&mut self, key: u8, pressed: bool) {
+        if self.key_events.len() == KEY_EVENTS_LENGTH {
+            self.key_events.pop_front();
         }
-        self.program_counter += 2;
+        self.key_events.push_back(KeyEvent {
+            cycle: self.cycles,
+            key,
+            pressed,
+        });
     }
-}
 
-#[allow(arithmetic_overflow)]
-#[cfg(test)]
-mod tests {
+    /** Plays back recorded input: each event is applied right before the instruction at its
+     * cycle (see `cycle_count`) executes. Replaces the events not applied yet */
+    pub fn replay(&mut self, events: impl IntoIterator<Item = KeyEvent>) {
+        let mut events: Vec<KeyEvent> = events.into_iter().collect();
+        events.sort_by_key(|event| event.cycle);
+        self.replay = events.into();
+    }
 
-    use super::*;
+    /** Packs the display into 1 bit per pixel rows (8 pixels per byte, MSB first), a pixel
+     * being set when it is lit in any plane */
+    pub fn framebuffer_packed(&self) -> Vec<u8> {
+        self.gfx
+            .chunks(8)
+            .map(|pixels| {
+                pixels
+                    .iter()
+                    .fold(0, |byte, pixel| (byte << 1) | u8::from(*pixel != 0))
+            })
+            .collect()
+    }
 
-    #[test]
-    fn init_state() {
-        let chip8 = Chip8::new();
-        let mut mem = [0u8; 4096];
+    /** The display as text for debugging, a line per row with '#' for the pixels lit in any
+     * plane and ' ' for the others */
+    pub fn framebuffer_ascii(&self) -> String {
+        self.gfx
+            .chunks(self.width)
+            .map(|row| {
+                let mut line: String = row
+                    .iter()
+                    .map(|&pixel| if pixel != 0 { '#' } else { ' ' })
+                    .collect();
+                line.push('\n');
+                line
+            })
+            .collect()
+    }
 
-        for (place, data) in mem.iter_mut().zip(CHIP8_FONT_SET.iter()) {
-            *place = *data
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            memory: self.memory.clone(),
+            v: self.v,
+            i: self.i,
+            program_counter: self.program_counter,
+            gfx: self.gfx.clone(),
+            width: self.width,
+            height: self.height,
+            selected_plane: self.selected_plane,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            stack: self.stack,
+            stack_pointer: self.stack_pointer,
         }
+    }
 
-        assert_eq!(chip8.memory, mem);
-        assert_eq!(chip8.v, [0u8; 16]);
-        assert_eq!(chip8.program_counter, 512);
-        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.delay_timer, 0);
-        assert_eq!(chip8.sound_timer, 0);
-        assert_eq!(chip8.stack, [0u16; 16]);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.keys, [0u8; 16]);
+    /** Restores a saved state. A paused machine stays paused, otherwise it runs again */
+    pub fn load_state(&mut self, state: &SaveState) {
+        self.memory.clone_from(&state.memory);
+        self.clear_decode_cache();
+        self.v = state.v;
+        self.i = state.i;
+        self.program_counter = state.program_counter;
+        self.gfx.clone_from(&state.gfx);
+        self.width = state.width;
+        self.height = state.height;
+        self.selected_plane = state.selected_plane;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.stack = state.stack;
+        self.stack_pointer = state.stack_pointer;
+        // The undo log belongs to the timeline left behind
+        self.undo.clear();
+        self.vblank_wait = false;
+        self.dirty = true;
+        if self.state != ChipState::Pause {
+            self.state = ChipState::Run;
+        }
     }
 
-    #[test]
-    fn get_op_code() {
-        let mut chip8 = Chip8::new();
-        let program_counter = 0x210;
-        chip8.program_counter = program_counter;
+    /** Takes a rewind snapshot every `frames` frames instead of REWIND_INTERVAL, 1 allows
+     * `rewind_frames` to go back to any frame. The history still keeps REWIND_LENGTH snapshots */
+    pub fn set_rewind_interval(&mut self, frames: u32) {
+        self.rewind_interval = frames.max(1);
+    }
+
+    /** Counts a frame, taking a rewind snapshot every rewind interval */
+    pub fn record_rewind(&mut self) {
+        self.frame_count += 1;
+        self.frames_since_snapshot += 1;
+        if self.frames_since_snapshot < self.rewind_interval {
+            return;
+        }
+        self.frames_since_snapshot = 0;
+        if self.rewind.len() == REWIND_LENGTH {
+            self.rewind.pop_front();
+        }
+        self.rewind.push_back((self.frame_count, self.save_state()));
+    }
+
+    /** Goes back `frames` frames, to the latest snapshot taken at or before that frame, or to
+     * the oldest snapshot when the history does not go back that far. The snapshots after
+     * it are dropped. Returns false when there is no snapshot */
+    pub fn rewind_frames(&mut self, frames: usize) -> bool {
+        let target = self.frame_count.saturating_sub(frames as u64);
+        while self.rewind.len() > 1 && self.rewind.back().is_some_and(|(frame, _)| *frame > target)
+        {
+            self.rewind.pop_back();
+        }
+        self.rewind_step()
+    }
+
+    /** Goes back one frame, for a rewind held down: the history plays backwards in real time,
+     * a snapshot is restored when its frame is reached, in between the display keeps the
+     * newer state. Returns false when there is no snapshot left */
+    pub fn rewind_frame(&mut self) -> bool {
+        let target = self.frame_count.saturating_sub(1);
+        while self.rewind.len() > 1 && self.rewind.back().is_some_and(|(frame, _)| *frame > target)
+        {
+            self.rewind.pop_back();
+        }
+        match self.rewind.back() {
+            Some((frame, _)) if *frame >= target => self.rewind_step(),
+            Some(_) => {
+                self.frame_count = target;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /** Goes back to the most recent rewind snapshot, returns false when there is none left */
+    pub fn rewind_step(&mut self) -> bool {
+        match self.rewind.pop_back() {
+            Some((frame, state)) => {
+                self.load_state(&state);
+                self.frame_count = frame;
+                self.frames_since_snapshot = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn load_game(&mut self, game_file_path: PathBuf) -> Result<(), String> {
+        let contents = fs::read(&game_file_path)
+            .map_err(|e| format!("could not read {}: {}", game_file_path.display(), e))?;
+        self.load_game_from_bytes(&contents)
+    }
+
+    /** Copies a ROM at the load address. A ROM must hold at least one instruction */
+    pub fn load_game_from_bytes(&mut self, rom: &[u8]) -> Result<(), String> {
+        if rom.len() < 2 {
+            return Err(format!(
+                "ROM too small: {} bytes, an instruction takes 2",
+                rom.len()
+            ));
+        }
+        let end = self.load_address + rom.len();
+        if end > self.memory.len() {
+            return Err(format!(
+                "ROM too large: {} bytes do not fit at {:#05x}",
+                rom.len(),
+                self.load_address
+            ));
+        }
+        self.memory[self.load_address..end].copy_from_slice(rom);
+        self.rom = rom.to_vec();
+        self.clear_decode_cache();
+        Ok(())
+    }
+
+    /** Runs the machine at 60 frames per second until it quits or halts on an error. The input
+     * is polled and the display presented once per iteration, which runs the frames that are
+     * due (see `FrameClock`) then sleeps until the next one. A deterministic machine runs a
+     * frame per iteration without sleeping */
+    pub fn run_loop(
+        &mut self,
+        display: &mut impl Display,
+        input: &mut impl Input,
+        sound: &mut impl Sound,
+    ) -> Result<(), String> {
+        // The first frame runs right away
+        let mut clock = FrameClock {
+            accumulated: FRAME_DURATION,
+        };
+        let mut last = Instant::now();
+        let mut paused = false;
+        let mut pattern = None;
+        loop {
+            let before = self.keys;
+            input.poll(&mut self.keys, &mut self.state);
+            self.queue_key_changes(before);
+            if let Some(cold) = input.reset_requested() {
+                self.reset(cold);
+            }
+            if paused != (self.state == ChipState::Pause) {
+                paused = !paused;
+                display.set_paused(paused);
+            }
+
+            if input.screenshot_requested() {
+                let seconds = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs());
+                let path = PathBuf::from(format!("screenshot-{}.png", seconds));
+                // A failed screenshot should not stop the game
+                if let Err(e) = display.save_screenshot(&self.gfx, self.width, self.height, &path) {
+                    error!("{}", e);
+                }
+            }
+
+            if input.stats_toggle_requested() {
+                display.toggle_stats();
+            }
+
+            if input.collision_debug_toggle_requested() {
+                self.set_collision_debug(!self.collision_debug);
+                display.set_highlight(&[]);
+                self.dirty = true;
+            }
+
+            self.set_turbo(input.turbo_held());
+
+            let frames = if self.deterministic {
+                1
+            } else {
+                let now = Instant::now();
+                let elapsed = now - last;
+                last = now;
+                clock.advance(elapsed)
+            };
+            for _ in 0..frames {
+                if input.rewind_held() {
+                    self.rewind_frame();
+                } else if self.state != ChipState::Pause {
+                    self.step();
+                    self.record_rewind();
+                }
+            }
+            let playing = self
+                .audio_pattern
+                .map(|audio_pattern| (audio_pattern, self.pitch));
+            if playing != pattern {
+                pattern = playing;
+                sound.set_pattern(self.audio_pattern, self.pattern_rate());
+            }
+            sound.play(!self.deterministic && self.is_beeping());
+            // Present once per frame, and only when the display changed. Collision highlights
+            // last one frame, so they are presented every frame
+            if self.take_dirty() || self.collision_debug {
+                if self.collision_debug {
+                    display.set_highlight(&self.collided);
+                }
+                display.draw(&self.gfx, self.width, self.height)?;
+            }
+            if frames > 0 {
+                display.frame_done(self.cycles);
+            }
+
+            self.halt_error()?;
+            if self.state == ChipState::Quit {
+                return Ok(());
+            }
+
+            if !self.deterministic {
+                thread::sleep(clock.until_next_frame().saturating_sub(last.elapsed()));
+            }
+        }
+    }
+
+    /** Pauses the machine before executing the instruction at `address`. `run_until_idle` and
+     * `run_to_next_draw` return at the breakpoint, `resume` runs its instruction */
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) {
+        self.breakpoints.remove(&address);
+    }
+
+    /** Pauses the machine after the instruction writing memory at `address` */
+    pub fn add_watchpoint(&mut self, address: usize) {
+        self.watchpoints.insert(address);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) {
+        self.watchpoints.remove(&address);
+    }
+
+    /** The (address, PC) of the last instruction that wrote a watched address */
+    pub fn watchpoint_hit(&self) -> Option<(usize, usize)> {
+        self.watchpoint_hit
+    }
+
+    /** Pauses on an unknown opcode, to inspect the machine and `skip_current_instruction`,
+     * instead of halting on an error */
+    pub fn set_pause_on_unknown_opcode(&mut self, enabled: bool) {
+        self.pause_on_unknown_opcode = enabled;
+    }
+
+    /** The (PC, opcode) of the unknown opcode the machine paused at */
+    pub fn unknown_opcode(&self) -> Option<(usize, u16)> {
+        self.unknown_opcode
+    }
+
+    /** Moves past the opcode at PC without executing it and resumes, e.g. after pausing on an
+     * unknown opcode */
+    pub fn skip_current_instruction(&mut self) {
+        self.program_counter = (self.program_counter + 2) % self.memory.len();
+        self.unknown_opcode = None;
+        self.state = ChipState::Run;
+    }
+
+    /** Pauses the machine when it spins for `cycles` instructions without changing a register
+     * or drawing, e.g. an EXA1 loop waiting for a key. None turns the watchdog off */
+    pub fn set_watchdog(&mut self, cycles: Option<u64>) {
+        self.watchdog = cycles;
+        self.watchdog_start = self.cycles;
+    }
+
+    /** Counts the cycles since the registers last changed or the display was drawn, and
+     * pauses once they reach the watchdog threshold */
+    fn check_watchdog(&mut self, threshold: u64) {
+        let state = (self.v, self.i, self.stack_pointer);
+        if state != self.watchdog_state || self.dirty {
+            self.watchdog_state = state;
+            self.watchdog_start = self.cycles;
+        } else if self.cycles - self.watchdog_start >= threshold {
+            warn!(
+                "no progress for {} cycles at {:#05x}, pausing",
+                threshold, self.program_counter
+            );
+            self.state = ChipState::Pause;
+            self.watchdog_start = self.cycles;
+        }
+    }
+
+    /** Restarts the loaded ROM: registers, stack, timers and display are reset, the settings
+     * and the RPL flags are kept. A warm reset keeps the RAM as the ROM left it, a cold reset
+     * clears the RAM from 0x200 and loads the ROM again */
+    pub fn reset(&mut self, cold: bool) {
+        if cold {
+            self.memory[LOAD_ADDRESS..].fill(self.fill);
+            self.copy_rom();
+            self.clear_decode_cache();
+        }
+        self.v = [0; 16];
+        self.i = 0;
+        self.program_counter = self.load_address;
+        self.stack = [0; 16];
+        self.stack_pointer = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.held_keys = Keypad::default();
+        self.key_events.clear();
+        self.set_hires(false);
+        self.selected_plane = 1;
+        self.state = ChipState::Run;
+        self.vblank_wait = false;
+        self.breakpoint_hit = None;
+        self.unknown_opcode = None;
+        self.dirty = true;
+        self.undo.clear();
+    }
+
+    /** Stops executing instructions until `resume` is called */
+    pub fn pause(&mut self) {
+        self.state = ChipState::Pause;
+    }
+
+    /** Continues execution after `pause` */
+    pub fn resume(&mut self) {
+        if self.state == ChipState::Pause {
+            self.state = ChipState::Run;
+        }
+    }
+
+    /** Runs without a display until the ROM jumps to itself (the usual end of test ROMs),
+     * waits for a key, exits, pauses, or `max_cycles` instructions were executed.
+     * Timers tick after each frame worth of instructions. Returns the instructions executed */
+    pub fn run_until_idle(&mut self, max_cycles: u64) -> Result<u64, String> {
+        let start = self.cycles;
+        let mut frame_cycles = self.frame_cycles();
+        while self.cycles - start < max_cycles {
+            if frame_cycles == 0 {
+                self.tick_timers();
+                frame_cycles = self.frame_cycles();
+                continue;
+            }
+            let program_counter = self.program_counter;
+            self.emulate_cycle();
+            self.halt_error()?;
+            if matches!(
+                self.state,
+                ChipState::Quit | ChipState::Block | ChipState::Pause
+            ) {
+                break;
+            }
+            if self.program_counter == program_counter
+                && self.last_op_code == 0x1000 | u16::try_from(program_counter).unwrap()
+            {
+                break;
+            }
+            frame_cycles -= 1;
+        }
+        Ok(self.cycles - start)
+    }
+
+    /** Runs without a display until the next DXYN was executed, to skip to the next visible
+     * change while debugging. Stops early when the machine waits for a key, exits, pauses, or
+     * after `max` instructions. Timers tick after each frame worth of instructions. Returns the
+     * instructions executed */
+    pub fn run_to_next_draw(&mut self, max: u64) -> Result<u64, String> {
+        let start = self.cycles;
+        let mut frame_cycles = self.frame_cycles();
+        while self.cycles - start < max {
+            if frame_cycles == 0 {
+                self.tick_timers();
+                frame_cycles = self.frame_cycles();
+                continue;
+            }
+            let transition = self.emulate_cycle();
+            self.halt_error()?;
+            if matches!(
+                transition.to,
+                ChipState::Draw | ChipState::Block | ChipState::Quit | ChipState::Pause
+            ) {
+                break;
+            }
+            frame_cycles -= 1;
+        }
+        Ok(self.cycles - start)
+    }
+
+    /** Runs one 60Hz frame without any I/O: the instructions of the frame, then a timer tick.
+     * A paused machine does not move.
+     * The keypad is latched at the start of the frame so that all the EX9E/EXA1 of the frame
+     * agree, like games polling the keys once per frame expect. The tradeoff is that a key
+     * change during the frame (a replayed event or `set_key`) is only seen by the skips on the
+     * next frame, and a tap shorter than a frame is missed by them */
+    pub fn step(&mut self) {
+        if self.state == ChipState::Pause {
+            return;
+        }
+        self.collided.clear();
+        self.latched_keys = Some(self.keys);
+        if self.vip_timing {
+            let factor = if self.turbo { self.turbo_factor } else { 1 };
+            self.machine_cycle_budget += VIP_CYCLES_PER_FRAME * i64::from(factor);
+            while self.machine_cycle_budget > 0 {
+                let start = self.machine_cycles;
+                let running = self.frame_cycle();
+                self.machine_cycle_budget -= i64::try_from(self.machine_cycles - start).unwrap();
+                if !running {
+                    self.machine_cycle_budget = 0;
+                    break;
+                }
+            }
+        } else {
+            for _ in 0..self.frame_cycles() {
+                if !self.frame_cycle() {
+                    break;
+                }
+            }
+        }
+        self.latched_keys = None;
+        self.tick_timers();
+    }
+
+    /** Executes an instruction of the frame, returns false when the rest of the frame is
+     * spent waiting */
+    fn frame_cycle(&mut self) -> bool {
+        let transition = self.emulate_cycle();
+        // FX0A only sees new keys on the next frame, a halted machine does not run at all.
+        // With the display wait quirk the rest of the frame is spent in DXYN
+        matches!(
+            transition.to,
+            ChipState::Run | ChipState::Draw | ChipState::Clear
+        ) && !self.vblank_wait
+    }
+
+    /** The error for a machine stopped by a stack or opcode error */
+    fn halt_error(&self) -> Result<(), String> {
+        match self.state {
+            ChipState::StackOverflow => {
+                Err(format!("Stack overflow at {:#05x}", self.program_counter))
+            }
+            ChipState::StackUnderflow => {
+                Err(format!("Stack underflow at {:#05x}", self.program_counter))
+            }
+            ChipState::Error(op_code) => Err(format!(
+                "Unknown opcode {:#06x} at {:#05x}",
+                op_code, self.program_counter
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /** Executes one instruction, returning the state the machine was in before and after */
+    pub fn emulate_cycle(&mut self) -> Transition {
+        while let Some(event) = self.replay.front().copied() {
+            if event.cycle > self.cycles {
+                break;
+            }
+            self.set_key(event.key, event.pressed);
+            self.replay.pop_front();
+        }
+
+        let from = self.state;
+        match self.state {
+            ChipState::Block => {}
+            ChipState::Pause => return Transition { from, to: from },
+            // The machine stays halted until it is reset
+            ChipState::Quit
+            | ChipState::StackOverflow
+            | ChipState::StackUnderflow
+            | ChipState::Error(_) => return Transition { from, to: from },
+            _ if self.vblank_wait => return Transition { from, to: from },
+            _ => self.state = ChipState::Run,
+        }
+
+        // A key wait is one instruction, it only breaks when it starts
+        if from != ChipState::Block
+            && self.breakpoints.contains(&self.program_counter)
+            && self.breakpoint_hit != Some(self.program_counter)
+        {
+            info!("breakpoint at {:#05x}", self.program_counter);
+            self.breakpoint_hit = Some(self.program_counter);
+            self.state = ChipState::Pause;
+            return Transition {
+                from,
+                to: self.state,
+            };
+        }
+        self.breakpoint_hit = None;
+
+        self.execute();
+        if let Some(threshold) = self.watchdog {
+            self.check_watchdog(threshold);
+        }
+        Transition {
+            from,
+            to: self.state,
+        }
+    }
+
+    /** Decrements the delay and sound timers, called at 60Hz */
+    pub fn tick_timers(&mut self) {
+        self.vblank_wait = false;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    /** Writes a byte of memory for an instruction, recording writes into the ROM while the
+     * code watch is on. A write to a watchpoint pauses once the instruction is done */
+    fn write(&mut self, address: usize, value: u8) {
+        if self.protect_reserved && address < LOAD_ADDRESS {
+            debug!("write to reserved address {:#05x} dropped", address);
+            return;
+        }
+        let rom_end = self.load_address + self.rom.len();
+        if self.code_watch && (self.load_address..rom_end).contains(&address) {
+            self.code_writes.push(address);
+        }
+        if self.watchpoints.contains(&address) {
+            info!(
+                "watchpoint {:#05x} written by {:#05x}",
+                address, self.program_counter
+            );
+            self.watchpoint_hit = Some((address, self.program_counter));
+            self.state = ChipState::Pause;
+        }
+        self.memory[address] = value;
+        self.invalidate_decode_cache(address);
+    }
+
+    /** Address I + offset, wrapped around the end of memory */
+    fn address(&self, offset: usize) -> usize {
+        (self.i + offset) % self.memory.len()
+    }
+
+    /** Reads the opcode at PC, the second byte of an opcode at 0xFFF is read from 0x000 */
+    fn get_op_code(&self) -> u16 {
+        let low = (self.program_counter + 1) % self.memory.len();
+        u16::from(self.memory[self.program_counter]) << 8 | u16::from(self.memory[low])
+    }
+
+    fn execute(&mut self) {
+        // Fetch and decode the opcode, a jump or skip past the end of memory wraps around
+        self.program_counter %= self.memory.len();
+        let (op_code, instruction) = match self.decode_cache.get(self.program_counter) {
+            Some(Some(cached)) => *cached,
+            _ => {
+                let op_code = self.get_op_code();
+                let instruction = decode(op_code);
+                if let Some(entry) = self.decode_cache.get_mut(self.program_counter) {
+                    *entry = Some((op_code, instruction));
+                }
+                (op_code, instruction)
+            }
+        };
+        self.cycles += 1;
+        self.machine_cycles += u64::from(instruction.cycle_cost());
+        self.last_op_code = op_code;
+        if self.trace_enabled {
+            if self.trace.len() == 2 * TRACE_LENGTH {
+                self.trace.drain(..TRACE_LENGTH);
+            }
+            self.trace.push((self.program_counter, op_code));
+        }
+
+        if let Some(pattern) = instruction.pattern() {
+            self.coverage.insert(pattern);
+        }
+        trace!(
+            "{:#05x}: {:04x} {}",
+            self.program_counter,
+            op_code,
+            instruction
+        );
+        if self.undo_enabled {
+            self.record_undo(instruction);
+        }
+
+        match instruction {
+            Instruction::Sys(nnn) => self.sys(nnn),
+            Instruction::ScrollDown(n) => self.scroll_down(n),
+            Instruction::ClearScreen => self.clear_screen(),
+            Instruction::Return => self.return_from_subroutine(),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::Lores => self.lores(),
+            Instruction::Hires => self.hires(),
+            Instruction::Jump(nnn) => self.goto(nnn),
+            Instruction::Call(nnn) => self.call_subroutine(nnn),
+            Instruction::SkipIfEqNn { x, nn } => self.skip_if_eq_to_nn(x, nn),
+            Instruction::SkipIfNotEqNn { x, nn } => self.skip_if_not_eq_to_nn(x, nn),
+            Instruction::SkipIfEqVy { x, y } => self.skip_if_vx_eq_to_vy(x, y),
+            Instruction::SetVxNn { x, nn } => self.set_vx_to_nn(x, nn),
+            Instruction::AddVxNn { x, nn } => self.add_nn_to_vx(x, nn),
+            Instruction::SetVxVy { x, y } => self.set_vx_to_vy(x, y),
+            Instruction::Or { x, y } => self.set_vx_to_vx_or_vy(x, y),
+            Instruction::And { x, y } => self.set_vx_to_vx_and_vy(x, y),
+            Instruction::Xor { x, y } => self.set_vx_to_vx_xor_vy(x, y),
+            Instruction::AddVxVy { x, y } => self.set_vx_to_vx_plus_vy(x, y),
+            Instruction::SubVxVy { x, y } => self.set_vx_to_vx_minus_vy(x, y),
+            Instruction::ShiftRight { x, y } => self.shift_right(x, y),
+            Instruction::SubVyVx { x, y } => self.set_vx_to_vy_minus_vx(x, y),
+            Instruction::ShiftLeft { x, y } => self.shift_left(x, y),
+            Instruction::SkipIfNotEqVy { x, y } => self.skip_if_vx_not_eq_vy(x, y),
+            Instruction::SetI(nnn) => self.set_i_to_nnn(nnn),
+            Instruction::JumpV0(nnn) => self.goto_nnn_plus_v0(nnn),
+            Instruction::Rand { x, nn } => self.set_vx_to_rand_and_nn(x, nn),
+            Instruction::Draw { x, y, n } => self.draw(x, y, n),
+            Instruction::SkipIfKey { x } => self.skip_if_key_pressed(x),
+            Instruction::SkipIfNotKey { x } => self.skip_if_not_key_pressed(x),
+            Instruction::GetDelayTimer { x } => self.set_vx_to_delay_timer(x),
+            Instruction::WaitKey { x } => self.is_key_press(x),
+            Instruction::SetDelayTimer { x } => self.set_delay_timer_to_vx(x),
+            Instruction::SetSoundTimer { x } => self.set_sound_timer_to_vx(x),
+            Instruction::AddVxToI { x } => self.add_vx_to_i(x),
+            Instruction::SetIToSprite { x } => self.set_i_to_sprite(x),
+            Instruction::SetIToBigSprite { x } => self.set_i_to_big_sprite(x),
+            Instruction::LongLoadI => self.long_load_i(op_code),
+            Instruction::SelectPlane { n } => self.select_plane(n),
+            Instruction::LoadAudioPattern => self.load_audio_pattern(),
+            Instruction::SetPitch { x } => self.set_pitch(x),
+            Instruction::Bcd { x } => self.bcd(x),
+            Instruction::RegDump { x } => self.reg_dump(x),
+            Instruction::RegLoad { x } => self.reg_load(x),
+            Instruction::SaveFlags { x } => self.save_flags_vx(x),
+            Instruction::LoadFlags { x } => self.load_flags_vx(x),
+            Instruction::Invalid(op_code) => self.invalid(op_code),
+        }
+    }
+
+    /** Keeps the scrolled pixels of the selected planes, the other planes do not move */
+    fn scroll_selected_planes(&mut self, scrolled: Vec<u8>) {
+        let planes = self.selected_plane;
+        for (pixel, scrolled) in self.gfx.iter_mut().zip(scrolled) {
+            *pixel = (*pixel & !planes) | (scrolled & planes);
+        }
+    }
+
+    // OP Codes
+    // Reference: https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
+
+    /** 0NNN: Calls the machine code routine at NNN on the original interpreters, ignored */
+    fn sys(&mut self, nnn: u16) {
+        debug!("SYS {:#05x} ignored", nnn);
+        self.program_counter += 2;
+    }
+
+    /** 0x00E0: Clears the selected planes of the screen */
+    fn clear_screen(&mut self) {
+        let planes = self.selected_plane;
+        self.gfx.iter_mut().for_each(|pixel| *pixel &= !planes);
+        self.state = ChipState::Clear;
+        self.dirty = true;
+        self.program_counter += 2;
+    }
+
+    /** 0x00CN: Scrolls the display down by N pixels (SCHIP) */
+    fn scroll_down(&mut self, n: u8) {
+        let offset = usize::from(n) * self.width;
+        let len = self.gfx.len();
+        let mut scrolled = self.gfx.clone();
+        scrolled.copy_within(0..len - offset, offset);
+        scrolled[..offset].fill(0);
+        self.scroll_selected_planes(scrolled);
+        self.state = ChipState::Draw;
+        self.dirty = true;
+        self.program_counter += 2;
+    }
+
+    /** 0x00FB: Scrolls the display right by 4 pixels (SCHIP) */
+    fn scroll_right(&mut self) {
+        let width = self.width;
+        let mut scrolled = self.gfx.clone();
+        for row in scrolled.chunks_mut(width) {
+            row.copy_within(0..width - 4, 4);
+            row[..4].fill(0);
+        }
+        self.scroll_selected_planes(scrolled);
+        self.state = ChipState::Draw;
+        self.dirty = true;
+        self.program_counter += 2;
+    }
+
+    /** 0x00FC: Scrolls the display left by 4 pixels (SCHIP) */
+    fn scroll_left(&mut self) {
+        let width = self.width;
+        let mut scrolled = self.gfx.clone();
+        for row in scrolled.chunks_mut(width) {
+            row.copy_within(4..width, 0);
+            row[width - 4..].fill(0);
+        }
+        self.scroll_selected_planes(scrolled);
+        self.state = ChipState::Draw;
+        self.dirty = true;
+        self.program_counter += 2;
+    }
+
+    /** 0x00FD: Exits the interpreter (SCHIP) */
+    fn exit(&mut self) {
+        self.state = ChipState::Quit;
+        self.program_counter += 2;
+    }
+
+    /** 0x00FE: Switches to the 64x32 low resolution display (SCHIP) */
+    fn lores(&mut self) {
+        self.set_hires(false);
+        self.state = ChipState::Clear;
+        self.dirty = true;
+        self.program_counter += 2;
+    }
+
+    /** 0x00FF: Switches to the 128x64 high resolution display (SCHIP) */
+    fn hires(&mut self) {
+        self.set_hires(true);
+        self.state = ChipState::Clear;
+        self.dirty = true;
+        self.program_counter += 2;
+    }
+
+    /** 0x00EE: Returns from subroutine */
+    fn return_from_subroutine(&mut self) {
+        if self.stack_pointer == 0 {
+            self.state = ChipState::StackUnderflow;
+            return;
+        }
+        self.stack_pointer -= 1;
+        self.program_counter = usize::from(self.stack[self.stack_pointer]);
+    }
+
+    /** 0x1NNN: goto NNN */
+    fn goto(&mut self, nnn: u16) {
+        self.program_counter = usize::from(nnn);
+    }
+
+    /** 2NNN: Calls subroutine at NNN, pushing the address of the next instruction, which
+     * wraps to 0x000 after a call in the last word of memory */
+    fn call_subroutine(&mut self, nnn: u16) {
+        if self.stack_pointer >= self.stack.len() {
+            self.state = ChipState::StackOverflow;
+            return;
+        }
+        let return_address = (self.program_counter + 2) % self.memory.len();
+        self.stack[self.stack_pointer] = u16::try_from(return_address).unwrap();
+        self.stack_pointer += 1;
+        self.program_counter = usize::from(nnn);
+    }
+
+    /** 3XNN: Skips the next instruction if VX equals NN */
+    fn skip_if_eq_to_nn(&mut self, x: usize, nn: u8) {
+        if self.v[x] == nn {
+            self.program_counter += 4;
+        } else {
+            self.program_counter += 2;
+        }
+    }
+
+    /** 4XNN: Skips the next instruction if VX does not equal NN */
+    fn skip_if_not_eq_to_nn(&mut self, x: usize, nn: u8) {
+        if self.v[x] != nn {
+            self.program_counter += 4;
+        } else {
+            self.program_counter += 2;
+        }
+    }
+
+    /** 5XY0: Skips the next instruction if VX equals VY */
+    fn skip_if_vx_eq_to_vy(&mut self, x: usize, y: usize) {
+        if self.v[x] == self.v[y] {
+            self.program_counter += 4;
+        } else {
+            self.program_counter += 2;
+        }
+    }
+
+    /** 6XNN: Sets VX to NN */
+    fn set_vx_to_nn(&mut self, x: usize, nn: u8) {
+        self.v[x] = nn;
+        self.program_counter += 2;
+    }
+
+    /** 7XNN: Adds NN to VX (wrapping). VF is not affected */
+    fn add_nn_to_vx(&mut self, x: usize, nn: u8) {
+        self.v[x] = self.v[x].wrapping_add(nn);
+        self.program_counter += 2;
+    }
+
+    /** 8XY0: Sets VX to the value of VY */
+    fn set_vx_to_vy(&mut self, x: usize, y: usize) {
+        self.v[x] = self.v[y];
+        self.program_counter += 2;
+    }
+
+    /** 8XY1: Sets VX to VX or VY (bitwise OR operation) */
+    fn set_vx_to_vx_or_vy(&mut self, x: usize, y: usize) {
+        self.v[x] |= self.v[y];
+        self.reset_vf_after_logic();
+        self.program_counter += 2;
+    }
+
+    /** 8XY2: Sets VX to VX and VY (bitwise AND operation)*/
+    fn set_vx_to_vx_and_vy(&mut self, x: usize, y: usize) {
+        self.v[x] &= self.v[y];
+        self.reset_vf_after_logic();
+        self.program_counter += 2;
+    }
+
+    /** 8XY3: Sets VX to VX xor VY */
+    fn set_vx_to_vx_xor_vy(&mut self, x: usize, y: usize) {
+        self.v[x] ^= self.v[y];
+        self.reset_vf_after_logic();
+        self.program_counter += 2;
+    }
+
+    /** The COSMAC VIP computes 8XY1/8XY2/8XY3 in a routine that leaves VF at 0 */
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+
+    /** 8XY4: Adds VY to VX. VF is set to 1 when there's an overflow, and to 0 when there is not.
+     * VF has to be written before VX: with X = F the sum overwrites the carry */
+    fn set_vx_to_vx_plus_vy(&mut self, x: usize, y: usize) {
+        let (result, overflow) = self.v[x].overflowing_add(self.v[y]);
+        self.v[0xF] = u8::from(overflow);
+        self.v[x] = result;
+        self.program_counter += 2;
+    }
+
+    /** 8XY5: VY is subtracted from VX. VF is set to 0 when there's an underflow, and 1 when there is not. (i.e. VF set to 1 if VX >= VY and 0 if not).
+     * VF has to be written before VX: with X = F the difference overwrites the flag */
+    fn set_vx_to_vx_minus_vy(&mut self, x: usize, y: usize) {
+        let (result, underflow) = self.v[x].overflowing_sub(self.v[y]);
+        self.v[0xF] = u8::from(!underflow);
+        self.v[x] = result;
+        self.program_counter += 2;
+    }
+
+    /** 8XY6: Stores the least significant bit of VX in VF and then shifts VX to the right by 1.
+     * With the shift quirk VY is shifted into VX instead. The order matters for 8FY6, where
+     * the shifted value ends up in VF */
+    fn shift_right(&mut self, x: usize, y: usize) {
+        let source = if self.quirks.shift {
+            self.v[y]
+        } else {
+            self.v[x]
+        };
+        self.v[0xF] = source & 0x01;
+        self.v[x] = source >> 1;
+        self.program_counter += 2;
+    }
+
+    /** 8XY7: Sets VX to VY minus VX. VF is set to 0 when there's an underflow, and 1 when there is not. (i.e. VF set to 1 if VY >= VX).
+     * VF has to be written before VX: with X = F the difference overwrites the flag */
+    fn set_vx_to_vy_minus_vx(&mut self, x: usize, y: usize) {
+        let (result, underflow) = self.v[y].overflowing_sub(self.v[x]);
+        self.v[0xF] = u8::from(!underflow);
+        self.v[x] = result;
+        self.program_counter += 2;
+    }
+
+    /** 8XYE: Stores the most significant bit of VX in VF and then shifts VX to the left by 1.
+     * With the shift quirk VY is shifted into VX instead. The order matters for 8FYE, where
+     * the shifted value ends up in VF */
+    fn shift_left(&mut self, x: usize, y: usize) {
+        let source = if self.quirks.shift {
+            self.v[y]
+        } else {
+            self.v[x]
+        };
+        self.v[0xF] = source >> 7;
+        self.v[x] = source << 1;
+        self.program_counter += 2;
+    }
+
+    /** 9XY0: Skips the next instruction if VX does not equal VY */
+    fn skip_if_vx_not_eq_vy(&mut self, x: usize, y: usize) {
+        if self.v[x] != self.v[y] {
+            self.program_counter += 4;
+        } else {
+            self.program_counter += 2;
+        }
+    }
+
+    /** ANNN: Sets I to the address NNN */
+    fn set_i_to_nnn(&mut self, nnn: u16) {
+        self.i = usize::from(nnn);
+        self.program_counter += 2;
+    }
+
+    /** BNNN: Jumps to the address NNN plus V0, wrapping around the end of memory.
+     * With the jump quirk BXNN jumps to XNN plus VX (SCHIP) */
+    fn goto_nnn_plus_v0(&mut self, nnn: u16) {
+        let offset = if self.quirks.jump {
+            self.v[usize::from(nnn >> 8)]
+        } else {
+            self.v[0]
+        };
+        self.program_counter = (usize::from(offset) + usize::from(nnn)) % self.memory.len();
+    }
+
+    /** CXNN: Sets VX to the result of a bitwise and operation on a random number (0 to 255) and NN */
+    fn set_vx_to_rand_and_nn(&mut self, x: usize, nn: u8) {
+        self.v[x] = self.rng.gen_range(0..=255) & nn;
+        self.program_counter += 2;
+    }
+
+    /** DXYN: Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height of N pixels.
+     * VF is set to 1 if any lit pixel is turned off, and to 0 if not.
+     * The starting coordinate wraps around the screen, pixels past the edges are clipped
+     * unless the draw quirk wraps them around to the opposite side.
+     * In hires mode DXY0 draws a 16x16 sprite stored as two bytes per row (SCHIP).
+     * The sprite is drawn into the selected planes, with both planes selected the plane 2
+     * sprite follows the plane 1 sprite in memory (XO-CHIP).
+     * With the display wait quirk execution stalls until the next timer tick */
+    fn draw(&mut self, x: usize, y: usize, n: u8) {
+        let x = usize::from(self.v[x]) % self.width;
+        let y = usize::from(self.v[y]) % self.height;
+        let (width, height) = if n == 0 && self.width == HIRES_WIDTH {
+            (16, 16)
+        } else {
+            (8, usize::from(n))
+        };
+        let collision = self.plot_sprite(x, y, width, height);
+        self.v[0x0F] = u8::from(collision);
+        self.draw_stats.draws += 1;
+        self.draw_stats.collisions += u64::from(collision);
+
+        self.state = ChipState::Draw;
+        self.dirty = true;
+        self.vblank_wait = self.quirks.display_wait;
+
+        self.program_counter += 2;
+    }
+
+    /** XORs the `width` x `height` sprite at I into the selected planes with its top left
+     * corner at (x, y), clipping or wrapping the pixels past the edges per the draw quirk.
+     * Returns whether a lit pixel was turned off */
+    fn plot_sprite(&mut self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        let bytes_per_row = width / 8;
+        let rows = if self.quirks.draw.wrap_vertical {
+            height
+        } else {
+            height.min(self.height - y)
+        };
+        let columns = if self.quirks.draw.wrap_horizontal {
+            width
+        } else {
+            width.min(self.width - x)
+        };
+        let mut collision = false;
+
+        let mut sprite = self.i;
+        for plane in [1, 2] {
+            if self.selected_plane & plane == 0 {
+                continue;
+            }
+            for y_offset in 0..rows {
+                let row = sprite + y_offset * bytes_per_row;
+                // Rows past the end of memory are not drawn
+                if row + bytes_per_row > self.memory.len() {
+                    break;
+                }
+                for x_offset in 0..columns {
+                    let pixel = self.memory[row + x_offset / 8];
+                    if (pixel & (0x80 >> (x_offset % 8))) != 0 {
+                        let index =
+                            (x + x_offset) % self.width + (y + y_offset) % self.height * self.width;
+                        if self.gfx[index] & plane != 0 {
+                            collision = true;
+                            if self.collision_debug {
+                                self.collided.push(index);
+                            }
+                        }
+                        self.gfx[index] ^= plane;
+                        self.draw_stats.pixels_flipped += 1;
+                    }
+                }
+            }
+            sprite += height * bytes_per_row;
+        }
+        collision
+    }
+
+    /** The keys read by EX9E/EXA1: the keys latched for the frame, or the live keys outside
+     * of `step` */
+    fn skip_keys(&self) -> Keypad {
+        self.latched_keys.unwrap_or(self.keys)
+    }
+
+    /** EX9E: Skips the next instruction if the key stored in VX is pressed */
+    fn skip_if_key_pressed(&mut self, x: usize) {
+        if self.skip_keys().is_pressed(self.v[x]) {
+            self.program_counter += 4;
+        } else {
+            self.program_counter += 2;
+        }
+    }
+
+    /** EXA1: Skips the next instruction if the key stored in VX is not pressed */
+    fn skip_if_not_key_pressed(&mut self, x: usize) {
+        if !self.skip_keys().is_pressed(self.v[x]) {
+            self.program_counter += 4;
+        } else {
+            self.program_counter += 2;
+        }
+    }
+
+    /** FX07: Sets VX to the value of the delay timer */
+    fn set_vx_to_delay_timer(&mut self, x: usize) {
+        self.v[x] = self.delay_timer;
+        self.program_counter += 2;
+    }
+
+    /** FX0A: A key press is awaited, and then stored in VX (blocking operation).
+     * The instruction is re-executed in the Block state until a key goes down; keys that were
+     * already held when the wait started have to be released first.
+     * The first key down queued since the wait started is taken, so the exact key is stored
+     * even when it was released before the instruction ran again. Keys changed directly on
+     * `keys` are not queued and are found by comparing the keypad with the held keys */
+    fn is_key_press(&mut self, x: usize) {
+        if self.state != ChipState::Block {
+            self.held_keys = self.keys;
+            self.key_events.clear();
+            self.state = ChipState::Block;
+        }
+        while let Some(event) = self.key_events.pop_front() {
+            if event.pressed {
+                self.key_pressed(x, event.key);
+                return;
+            }
+        }
+        for key in 0..16 {
+            if !self.keys.is_pressed(key) {
+                self.held_keys.release(key);
+            }
+        }
+
+        if let Some(key) = self.keys.newly_pressed(&self.held_keys) {
+            self.key_pressed(x, key);
+        }
+    }
+
+    /** Ends the FX0A wait with `key` */
+    fn key_pressed(&mut self, x: usize, key: u8) {
+        debug!("FX0A key {} was pressed", key);
+        self.v[x] = key;
+        self.state = ChipState::Run;
+        self.program_counter += 2;
+    }
+
+    /** FX15: Sets the delay timer to VX */
+    fn set_delay_timer_to_vx(&mut self, x: usize) {
+        self.delay_timer = self.v[x];
+        self.program_counter += 2;
+    }
+
+    /**  FX18: Sets the sound timer to VX */
+    fn set_sound_timer_to_vx(&mut self, x: usize) {
+        self.sound_timer = self.v[x];
+        if self.sound_timer > 0 {
+            debug!("beep for {} frames", self.sound_timer);
+        }
+        self.program_counter += 2;
+    }
+
+    /** FX1E: Adds VX to I, wrapping around the end of memory. VF is not affected, unless the
+     * I overflow quirk (Amiga interpreters) sets it to 1 when I goes past 0xFFF, 0 otherwise */
+    fn add_vx_to_i(&mut self, x: usize) {
+        let sum = self.i + usize::from(self.v[x]);
+        if self.quirks.i_overflow {
+            self.v[0xF] = u8::from(sum > 0xFFF);
+        }
+        self.i = sum % self.memory.len();
+        self.program_counter += 2;
+    }
+
+    /** FX29: Sets I to the location of the sprite for the character in the low nibble of VX */
+    fn set_i_to_sprite(&mut self, x: usize) {
+        self.i = FONT_BASE + usize::from(self.v[x] & 0xf) * 0x5;
+        self.program_counter += 2;
+    }
+
+    /** FX30: Sets I to the location of the 10 byte sprite for the digit in the low nibble of VX
+     * (SCHIP) */
+    fn set_i_to_big_sprite(&mut self, x: usize) {
+        self.i = BIGFONT_BASE + usize::from(self.v[x] & 0xf) * 10;
+        self.program_counter += 2;
+    }
+
+    /** An opcode this machine does not know halts it with an error, or pauses it */
+    fn invalid(&mut self, op_code: u16) {
+        if self.pause_on_unknown_opcode {
+            warn!(
+                "unknown opcode {:#06x} at {:#05x}, pausing",
+                op_code, self.program_counter
+            );
+            self.unknown_opcode = Some((self.program_counter, op_code));
+            self.state = ChipState::Pause;
+        } else {
+            self.state = ChipState::Error(op_code);
+        }
+    }
+
+    /** FN01: Selects the planes used by DXYN, 00E0 and the scrolls, N being a mask of
+     * plane 1 (1), plane 2 (2) or both (3) (XO-CHIP) */
+    fn select_plane(&mut self, n: u8) {
+        self.selected_plane = n & 0x3;
+        self.program_counter += 2;
+    }
+
+    /** F000 NNNN: Sets I to the 16 bit address NNNN stored after the opcode (XO-CHIP) */
+    fn long_load_i(&mut self, op_code: u16) {
+        if self.memory.len() != XO_CHIP_MEMORY_SIZE {
+            self.invalid(op_code);
+            return;
+        }
+        let high = self.memory[(self.program_counter + 2) % self.memory.len()];
+        let low = self.memory[(self.program_counter + 3) % self.memory.len()];
+        self.i = usize::from(u16::from_be_bytes([high, low]));
+        self.program_counter += 4;
+    }
+
+    /** F002: Loads the 16 byte audio pattern from memory at I (XO-CHIP) */
+    fn load_audio_pattern(&mut self) {
+        let mut pattern = [0; 16];
+        for (n, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.memory[self.address(n)];
+        }
+        self.audio_pattern = Some(pattern);
+        self.program_counter += 2;
+    }
+
+    /** FX3A: Sets the audio pattern pitch to VX (XO-CHIP) */
+    fn set_pitch(&mut self, x: usize) {
+        self.pitch = self.v[x];
+        self.program_counter += 2;
+    }
+
+    /** FX33: Stores the binary-coded decimal representation of VX, with the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2 */
+    fn bcd(&mut self, x: usize) {
+        let val = self.v[x];
+        let (hundreds, tens, ones) = (self.address(0), self.address(1), self.address(2));
+        self.write(hundreds, val / 100);
+        self.write(tens, (val / 10) % 10);
+        self.write(ones, (val % 100) % 10);
+        self.program_counter += 2;
+    }
+
+    /**  FX55: Stores from V0 to VX (including VX) in memory, starting at address I. The offset from I is increased by 1 for each value written, but I itself is left unmodified, unless the load/store quirk increments it by X + 1 */
+    fn reg_dump(&mut self, x: usize) {
+        for n in 0..(x + 1) {
+            let address = self.address(n);
+            self.write(address, self.v[n])
+        }
+        if self.quirks.load_store {
+            self.i = self.address(x + 1);
+        }
+        self.program_counter += 2;
+    }
+
+    /** FX65: Fills from V0 to VX (including VX) with values from memory, starting at address I. The offset from I is increased by 1 for each value read, but I itself is left unmodified, unless the load/store quirk increments it by X + 1 */
+    fn reg_load(&mut self, x: usize) {
+        for n in 0..(x + 1) {
+            self.v[n] = self.memory[self.address(n)]
+        }
+        if self.quirks.load_store {
+            self.i = self.address(x + 1);
+        }
+        self.program_counter += 2;
+    }
+
+    /** FX75: Stores V0 to VX (including VX) in the RPL user flags, X is at most 7 (SCHIP) */
+    fn save_flags_vx(&mut self, x: usize) {
+        let len = (x + 1).min(self.flags.len());
+        self.flags[..len].copy_from_slice(&self.v[..len]);
+        self.program_counter += 2;
+    }
+
+    /** FX85: Fills V0 to VX (including VX) from the RPL user flags, X is at most 7 (SCHIP) */
+    fn load_flags_vx(&mut self, x: usize) {
+        let len = (x + 1).min(self.flags.len());
+        self.v[..len].copy_from_slice(&self.flags[..len]);
+        self.program_counter += 2;
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Mutex;
+
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+
+    use super::*;
+
+    /** Keeps the trace lines logged by all the tests */
+    struct TestLogger {
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl Log for TestLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Trace
+        }
+
+        fn log(&self, record: &Record) {
+            if let (Level::Trace, Ok(mut lines)) = (record.level(), self.lines.lock()) {
+                lines.push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger {
+        lines: Mutex::new(Vec::new()),
+    };
+
+    /** Sets up a machine for a test, e.g.
+     * `Chip8Builder::new().reg(0xA, 0x56).pc(0x210).at(0x210, [0x3a, 0x56]).build()` */
+    struct Chip8Builder {
+        chip8: Chip8,
+    }
+
+    impl Chip8Builder {
+        fn new() -> Chip8Builder {
+            Chip8Builder {
+                chip8: Chip8::new(),
+            }
+        }
+
+        fn quirks(mut self, quirks: Quirks) -> Chip8Builder {
+            self.chip8.set_quirks(quirks);
+            self
+        }
+
+        fn reg(mut self, x: usize, value: u8) -> Chip8Builder {
+            self.chip8.v[x] = value;
+            self
+        }
+
+        fn i(mut self, i: usize) -> Chip8Builder {
+            self.chip8.i = i;
+            self
+        }
+
+        fn pc(mut self, program_counter: usize) -> Chip8Builder {
+            self.chip8.program_counter = program_counter;
+            self
+        }
+
+        /** Copies bytes into memory starting at `address` */
+        fn at(mut self, address: usize, bytes: impl AsRef<[u8]>) -> Chip8Builder {
+            let bytes = bytes.as_ref();
+            self.chip8.memory[address..address + bytes.len()].copy_from_slice(bytes);
+            self
+        }
+
+        fn build(self) -> Chip8 {
+            self.chip8
+        }
+    }
+
+    #[test]
+    fn builder_sets_state() {
+        let chip8 = Chip8Builder::new()
+            .reg(0xA, 0x56)
+            .i(0x300)
+            .pc(0x210)
+            .at(0x210, [0x3a, 0x56])
+            .build();
+
+        assert_eq!(chip8.v[0xA], 0x56);
+        assert_eq!(chip8.i, 0x300);
+        assert_eq!(chip8.program_counter, 0x210);
+        assert_eq!(chip8.get_op_code(), 0x3a56);
+    }
+
+    #[test]
+    fn init_with_fill() {
+        let mut chip8 = Chip8::with_seed(1);
+        chip8.set_fill(0xaa);
+
+        assert!(chip8.memory[..FONT_BASE].iter().all(|&byte| byte == 0xaa));
+        assert_eq!(chip8.memory[FONT_BASE..FONT_BASE + 80], CHIP8_FONT_SET);
+        assert_eq!(
+            chip8.memory[BIGFONT_BASE..BIGFONT_BASE + 100],
+            SCHIP_BIGFONT_SET
+        );
+        let font_end = BIGFONT_BASE + SCHIP_BIGFONT_SET.len();
+        assert!(chip8.memory[font_end..LOAD_ADDRESS]
+            .iter()
+            .all(|&byte| byte == 0xaa));
+
+        chip8
+            .load_game_from_bytes(&[0x12, 0x00, 0x00, 0xe0])
+            .unwrap();
+
+        assert_eq!(chip8.memory[0x200..0x204], [0x12, 0x00, 0x00, 0xe0]);
+        assert_eq!(chip8.memory[0x204], 0xaa);
+
+        // memory cleared later gets the fill byte too
+        chip8.memory[0x300] = 0;
+        chip8.reset(true);
+        assert_eq!(chip8.memory[0x300], 0xaa);
+        chip8.set_xo_chip(true);
+        assert_eq!(chip8.memory[0xffff], 0xaa);
+
+        // a ROM loaded before the fill is kept, at its entry point
+        let mut chip8 = Chip8::new_with_entry(0x600);
+        chip8.load_game_from_bytes(&[0x12, 0x00]).unwrap();
+        chip8.set_fill(0xff);
+        assert_eq!(chip8.memory[0x5ff..0x603], [0xff, 0x12, 0x00, 0xff]);
+        assert_eq!(chip8.memory[FONT_BASE..FONT_BASE + 80], CHIP8_FONT_SET);
+    }
+
+    #[test]
+    fn large_rom_after_leaving_xo_chip() {
+        let mut chip8 = Chip8::new();
+        chip8.set_xo_chip(true);
+        let rom: Vec<u8> = (0..5000).map(|n| (n % 251) as u8).collect();
+        chip8.load_game_from_bytes(&rom).unwrap();
+        chip8.set_xo_chip(false);
+
+        // the part of the ROM past 4K is dropped
+        chip8.reset(true);
+        assert_eq!(chip8.memory.len(), 4096);
+        assert_eq!(chip8.memory[0x200..], rom[..4096 - 0x200]);
+
+        chip8.set_fill(0xaa);
+        assert_eq!(chip8.memory[0x200..], rom[..4096 - 0x200]);
+    }
+
+    #[test]
+    fn init_state() {
+        let chip8 = Chip8::new();
+        let mut mem = [0u8; 4096];
+
+        for (place, data) in mem[FONT_BASE..].iter_mut().zip(CHIP8_FONT_SET.iter()) {
+            *place = *data
+        }
+        for (place, data) in mem[BIGFONT_BASE..].iter_mut().zip(SCHIP_BIGFONT_SET.iter()) {
+            *place = *data
+        }
+
+        assert_eq!(chip8.memory, mem);
+        assert_eq!(chip8.v, [0u8; 16]);
+        assert_eq!(chip8.program_counter, 512);
+        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.delay_timer, 0);
+        assert_eq!(chip8.sound_timer, 0);
+        assert_eq!(chip8.stack, [0u16; 16]);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.keys, Keypad::default());
+        assert_eq!(chip8.held_keys, Keypad::default());
+    }
+
+    #[test]
+    fn load_game_at_entry_address() {
+        let mut chip8 = Chip8::new_with_entry(0x600);
+
+        chip8
+            .load_game_from_bytes(&[0x6a, 0x42, 0x12, 0x00])
+            .unwrap();
+
+        assert_eq!(chip8.program_counter, 0x600);
+        assert_eq!(chip8.memory[0x200], 0);
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.last_opcode(), 0x6a42);
+        assert_eq!(chip8.v[0x0a], 0x42);
+    }
+
+    #[test]
+    fn load_game_too_large() {
+        let mut chip8 = Chip8::new();
+
+        assert!(chip8.load_game_from_bytes(&[0; 4096 - 0x200]).is_ok());
+        assert_eq!(
+            chip8.load_game_from_bytes(&[0; 4096 - 0x1ff]),
+            Err("ROM too large: 3585 bytes do not fit at 0x200".to_string())
+        );
+    }
+
+    #[test]
+    fn load_game_too_small() {
+        let mut chip8 = Chip8::new();
+
+        assert_eq!(
+            chip8.load_game_from_bytes(&[]),
+            Err("ROM too small: 0 bytes, an instruction takes 2".to_string())
+        );
+        assert!(chip8.load_game_from_bytes(&[0x12]).is_err());
+        assert!(chip8.load_game_from_bytes(&[0x12, 0x00]).is_ok());
+    }
+
+    #[test]
+    fn tick_timers() {
+        let mut chip8 = Chip8::new();
+        chip8.delay_timer = 2;
+        chip8.sound_timer = 1;
+
+        chip8.tick_timers();
+
+        assert_eq!(chip8.delay_timer, 1);
+        assert_eq!(chip8.sound_timer, 0);
+
+        chip8.tick_timers();
+        chip8.tick_timers();
+
+        assert_eq!(chip8.delay_timer, 0);
+        assert_eq!(chip8.sound_timer, 0);
+    }
+
+    #[test]
+    fn pause_resume() {
+        let mut chip8 = Chip8::new();
+        // 0x200: goto 0x200
+        chip8.memory[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+
+        chip8.pause();
+        chip8.emulate_cycle();
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Pause);
+        assert_eq!(chip8.cycle_count(), 0);
+
+        chip8.resume();
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.cycle_count(), 1);
+    }
+
+    #[test]
+    fn reset_warm() {
+        let mut chip8 = Chip8::new();
+        // V0 = 0x42, I = 0x300, [I] = V0, loop
+        chip8
+            .load_game_from_bytes(&[0x60, 0x42, 0xa3, 0x00, 0xf0, 0x55, 0x12, 0x06])
+            .unwrap();
+        for _ in 0..4 {
+            chip8.emulate_cycle();
+        }
+        chip8.gfx[5] = 1;
+
+        chip8.reset(false);
+
+        assert_eq!(chip8.memory[0x300], 0x42);
+        assert_eq!(chip8.program_counter, 0x200);
+        assert_eq!(chip8.v[0x0], 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
+        assert_eq!(chip8.state, ChipState::Run);
+    }
+
+    #[test]
+    fn reset_cold() {
+        let mut chip8 = Chip8::new();
+        // V0 = 0x42, I = 0x300, [I] = V0, I = 0x200, [I] = V0, loop
+        let rom = [
+            0x60, 0x42, 0xa3, 0x00, 0xf0, 0x55, 0xa2, 0x00, 0xf0, 0x55, 0x12, 0x0a,
+        ];
+        chip8.load_game_from_bytes(&rom).unwrap();
+        for _ in 0..6 {
+            chip8.emulate_cycle();
+        }
+        assert_eq!(chip8.memory[0x200], 0x42);
+
+        chip8.reset(true);
+
+        assert_eq!(chip8.memory[0x300], 0);
+        // the overwritten ROM byte is loaded again
+        assert_eq!(chip8.memory[0x200..0x20c], rom);
+        assert_eq!(chip8.memory[FONT_BASE..FONT_BASE + 80], CHIP8_FONT_SET);
+        assert_eq!(chip8.program_counter, 0x200);
+    }
+
+    #[test]
+    fn start_paused() {
+        let mut chip8 = Chip8::new();
+        chip8.load_game_from_bytes(&[0x12, 0x00]).unwrap();
+        chip8.pause();
+
+        chip8.step();
+        chip8.step();
+
+        assert_eq!(chip8.cycle_count(), 0);
+
+        chip8.resume();
+        chip8.step();
+
+        assert_eq!(chip8.cycle_count(), u64::from(CYCLES_PER_FRAME));
+    }
+
+    #[test]
+    fn watchdog_pauses_key_spin() {
+        let mut chip8 = Chip8::new();
+        chip8.set_watchdog(Some(100));
+        // V0 = 5, then loop until key 5 is pressed
+        chip8
+            .load_game_from_bytes(&[0x60, 0x05, 0xe0, 0xa1, 0x12, 0x08, 0x12, 0x02, 0x12, 0x08])
+            .unwrap();
+
+        for _ in 0..100 {
+            chip8.emulate_cycle();
+        }
+        assert_eq!(chip8.state, ChipState::Run);
+
+        chip8.emulate_cycle();
+        assert_eq!(chip8.state, ChipState::Pause);
+        assert_eq!(chip8.cycle_count(), 101);
+    }
+
+    #[test]
+    fn breakpoint_pauses_before_instruction() {
+        let mut chip8 = Chip8::new();
+        // V0 = 1, V1 = 2, V2 = 3, loop
+        chip8
+            .load_game_from_bytes(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x06])
+            .unwrap();
+        chip8.add_breakpoint(0x204);
+
+        chip8.step();
+
+        assert_eq!(chip8.state, ChipState::Pause);
+        assert_eq!(chip8.program_counter, 0x204);
+        assert_eq!(chip8.v[..3], [1, 2, 0]);
+        assert_eq!(chip8.cycles, 2);
+
+        // resuming runs the instruction at the breakpoint
+        chip8.resume();
+        chip8.step();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.v[..3], [1, 2, 3]);
+
+        // hit again after a reset, not once removed
+        chip8.reset(false);
+        chip8.step();
+        assert_eq!(chip8.state, ChipState::Pause);
+
+        chip8.remove_breakpoint(0x204);
+        chip8.reset(false);
+        chip8.step();
+        assert_eq!(chip8.state, ChipState::Run);
+    }
+
+    #[test]
+    fn watchpoint_pauses_after_write() {
+        let mut chip8 = Chip8::new();
+        // I = 0x300, V0..V2 = 1, 2, 3, then [I] = V0..V2 and loop
+        chip8
+            .load_game_from_bytes(&[
+                0xa3, 0x00, 0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xf2, 0x55, 0x12, 0x0a,
+            ])
+            .unwrap();
+        chip8.add_watchpoint(0x301);
+
+        chip8.step();
+
+        assert_eq!(chip8.state, ChipState::Pause);
+        assert_eq!(chip8.watchpoint_hit(), Some((0x301, 0x208)));
+        // the whole FX55 ran
+        assert_eq!(chip8.memory[0x300..0x303], [1, 2, 3]);
+        assert_eq!(chip8.program_counter, 0x20a);
+
+        chip8.remove_watchpoint(0x301);
+        chip8.resume();
+        chip8.step();
+        assert_eq!(chip8.state, ChipState::Run);
+    }
+
+    #[test]
+    fn watchdog_off() {
+        let mut chip8 = Chip8::new();
+        chip8.load_game_from_bytes(&[0x12, 0x00]).unwrap();
+
+        for _ in 0..1000 {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(chip8.state, ChipState::Run);
+    }
+
+    #[test]
+    fn fuzz_random_memory() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for run in 0..1000 {
+            let mut chip8 = Chip8::deterministic(run);
+            chip8.set_xo_chip(run % 2 == 0);
+            chip8.set_hires(run % 3 == 0);
+            rng.fill(&mut chip8.memory[LOAD_ADDRESS..MEMORY_SIZE]);
+            chip8.i = rng.gen_range(0..chip8.memory.len());
+            chip8.keys.set(rng.gen_range(0..16), true);
+
+            for _ in 0..500 {
+                chip8.emulate_cycle();
+                if !matches!(
+                    chip8.state,
+                    ChipState::Run | ChipState::Draw | ChipState::Clear | ChipState::Block
+                ) {
+                    break;
+                }
+                if chip8.vblank_wait {
+                    chip8.tick_timers();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn save_state_load_state() {
+        let mut chip8 = Chip8::new();
+        chip8.v[0x03] = 0x42;
+        chip8.i = 0x300;
+        chip8.gfx[10] = 1;
+        let state = chip8.save_state();
+
+        chip8.v[0x03] = 0;
+        chip8.i = 0;
+        chip8.gfx[10] = 0;
+        chip8.program_counter = 0x400;
+        chip8.load_state(&state);
+
+        assert_eq!(chip8.save_state(), state);
+        assert_eq!(chip8.v[0x03], 0x42);
+        assert_eq!(chip8.program_counter, 0x200);
+    }
+
+    #[test]
+    fn rewind_step() {
+        let mut chip8 = Chip8::new();
+        // 0x200: V0 += 1, 0x202: goto 0x200
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+
+        let mut snapshots = Vec::new();
+        for frame in 1..=14 {
+            chip8.emulate_cycle();
+            chip8.emulate_cycle();
+            chip8.tick_timers();
+            chip8.record_rewind();
+            if frame % REWIND_INTERVAL == 0 {
+                snapshots.push(chip8.save_state());
+            }
+        }
+
+        assert_eq!(chip8.v[0], 14);
+
+        assert!(chip8.rewind_step());
+        assert_eq!(chip8.save_state(), snapshots[1]);
+        assert_eq!(chip8.v[0], 12);
+
+        assert!(chip8.rewind_step());
+        assert_eq!(chip8.save_state(), snapshots[0]);
+        assert_eq!(chip8.v[0], 6);
+
+        assert!(!chip8.rewind_step());
+        assert_eq!(chip8.v[0], 6);
+    }
+
+    #[test]
+    fn rewind_frames() {
+        let mut chip8 = Chip8::new();
+        chip8.set_rewind_interval(1);
+        // 0x200: V0 += 1, 0x202: draw the font sprite of V0, 0x204: goto 0x200
+        chip8.memory[0x200..0x206].copy_from_slice(&[0x70, 0x01, 0xf0, 0x29, 0xd1, 0x15]);
+        chip8.memory[0x206..0x208].copy_from_slice(&[0x12, 0x00]);
+
+        let mut snapshots = Vec::new();
+        for _ in 0..5 {
+            for _ in 0..4 {
+                chip8.emulate_cycle();
+            }
+            chip8.tick_timers();
+            chip8.record_rewind();
+            snapshots.push(chip8.save_state());
+        }
+
+        assert!(chip8.rewind_frames(3));
+        assert_eq!(chip8.save_state(), snapshots[1]);
+        assert_eq!(chip8.gfx, snapshots[1].gfx);
+
+        // clamped to the oldest snapshot, from frame 1
+        assert!(chip8.rewind_frames(10));
+        assert_eq!(chip8.save_state(), snapshots[0]);
+        assert!(!chip8.rewind_frames(1));
+    }
+
+    #[test]
+    fn rewind_frame_real_time() {
+        let mut chip8 = Chip8::new();
+        // 0x200: V0 += 1, 0x202: goto 0x200
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        for _ in 0..60 {
+            chip8.emulate_cycle();
+            chip8.emulate_cycle();
+            chip8.tick_timers();
+            chip8.record_rewind();
+        }
+        assert_eq!(chip8.v[0], 60);
+
+        // a snapshot interval held goes back as many frames, not as many snapshots
+        for _ in 0..REWIND_INTERVAL - 1 {
+            assert!(chip8.rewind_frame());
+            assert_eq!(chip8.v[0], 60);
+        }
+        assert!(chip8.rewind_frame());
+        assert_eq!(chip8.v[0], 54);
+        assert_eq!(chip8.frame_count, 54);
+
+        for _ in 0..3 * REWIND_INTERVAL {
+            chip8.rewind_frame();
+        }
+        assert_eq!(chip8.v[0], 36);
+
+        // down to the oldest snapshot, from frame 6
+        while chip8.rewind_frame() {}
+        assert_eq!(chip8.v[0], 6);
+    }
+
+    #[test]
+    fn rewind_history_is_bounded() {
+        let mut chip8 = Chip8::new();
+
+        for _ in 0..(REWIND_LENGTH as u32 + 10) * REWIND_INTERVAL {
+            chip8.record_rewind();
+        }
+
+        assert_eq!(chip8.rewind.len(), REWIND_LENGTH);
+    }
+
+    #[test]
+    fn clock_hz_frame_cycles() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.frame_cycles(), CYCLES_PER_FRAME);
+
+        chip8.set_clock_hz(500);
+        let frames: Vec<u32> = (0..60).map(|_| chip8.frame_cycles()).collect();
+
+        // one second of frames executes exactly 500 instructions
+        assert_eq!(frames.iter().sum::<u32>(), 500);
+        assert!(frames.iter().all(|cycles| *cycles == 8 || *cycles == 9));
+
+        chip8.set_cycles_per_frame(20);
+        assert_eq!(chip8.frame_cycles(), 20);
+
+        // too fast to count is as fast as possible
+        chip8.set_cycles_per_frame(100_000_000);
+        chip8.set_turbo(true);
+        assert_eq!(chip8.frame_cycles(), u32::MAX / 60 * TURBO_FACTOR);
+        chip8.set_turbo_factor(u32::MAX);
+        assert_eq!(chip8.frame_cycles(), u32::MAX);
+    }
+
+    #[test]
+    fn turbo_frame_cycles() {
+        let mut chip8 = Chip8::new();
+
+        chip8.set_turbo(true);
+        assert_eq!(chip8.frame_cycles(), CYCLES_PER_FRAME * TURBO_FACTOR);
+
+        chip8.set_turbo_factor(3);
+        assert_eq!(chip8.frame_cycles(), CYCLES_PER_FRAME * 3);
+
+        chip8.set_turbo(false);
+        assert_eq!(chip8.frame_cycles(), CYCLES_PER_FRAME);
+    }
+
+    fn quirks(chip8: &Chip8) -> (bool, bool, bool, bool, bool, DrawQuirk, usize) {
+        (
+            chip8.quirks.shift,
+            chip8.quirks.load_store,
+            chip8.quirks.jump,
+            chip8.quirks.logic_vf_reset,
+            chip8.quirks.display_wait,
+            chip8.quirks.draw,
+            chip8.memory.len(),
+        )
+    }
+
+    #[test]
+    fn set_profile() {
+        let mut chip8 = Chip8::new();
+        let clip = DrawQuirk::default();
+        let wrap = DrawQuirk {
+            wrap_horizontal: true,
+            wrap_vertical: true,
+        };
+
+        chip8.set_profile(Profile::CosmacVip);
+        assert_eq!(quirks(&chip8), (true, true, false, true, true, clip, 4096));
+
+        chip8.set_profile(Profile::SuperChip);
+        assert_eq!(
+            quirks(&chip8),
+            (false, false, true, false, false, clip, 4096)
+        );
+
+        chip8.set_profile(Profile::XoChip);
+        assert_eq!(
+            quirks(&chip8),
+            (true, true, false, false, false, wrap, 65536)
+        );
+
+        chip8.set_profile(Profile::Modern);
+        assert_eq!(
+            quirks(&chip8),
+            (false, false, false, false, false, clip, 4096)
+        );
+        assert_eq!(quirks(&chip8), quirks(&Chip8::new()));
+    }
+
+    #[test]
+    fn with_quirks() {
+        let quirks = Quirks {
+            shift: true,
+            load_store: true,
+            jump: true,
+            logic_vf_reset: true,
+            i_overflow: true,
+            display_wait: true,
+            draw: DrawQuirk {
+                wrap_horizontal: true,
+                wrap_vertical: true,
+            },
+        };
+        assert_eq!(Chip8::with_quirks(quirks).quirks(), quirks);
+        assert_eq!(Chip8::new().quirks(), Quirks::default());
+
+        // V1 = 4, V2 = 0x10, V3 = 60, VF = 0x42, I = 0xFF8
+        let run = |op_code: [u8; 2]| {
+            let mut chip8 = Chip8Builder::new()
+                .quirks(quirks)
+                .reg(0x1, 0x04)
+                .reg(0x2, 0x10)
+                .reg(0x3, 60)
+                .reg(0xF, 0x42)
+                .i(0xff8)
+                .pc(0x200)
+                .at(0x200, op_code)
+                .at(0xff8, [0xff])
+                .build();
+            chip8.execute();
+            chip8
+        };
+
+        // SHR V0, V1 shifts V1
+        assert_eq!(run([0x80, 0x16]).v[0x0], 0x02);
+        // LD [I], V0 moves I
+        assert_eq!(run([0xf0, 0x55]).i, 0xff9);
+        // JP V0, 0x210 is JP V2, 0x210
+        assert_eq!(run([0xb2, 0x10]).program_counter, 0x220);
+        // OR V1, V2 resets VF
+        assert_eq!(run([0x81, 0x21]).v[0xF], 0);
+        // ADD I, V2 past 0xFFF sets VF
+        assert_eq!(run([0xf2, 0x1e]).v[0xF], 1);
+        // DRW V3, V0, 1 at x = 60 wraps, and waits for the next frame
+        let chip8 = run([0xd3, 0x01]);
+        assert_eq!(chip8.gfx[..4], [1; 4]);
+        assert!(chip8.vblank_wait);
+    }
+
+    #[test]
+    fn parse_profile() {
+        assert_eq!("schip".parse(), Ok(Profile::SuperChip));
+        assert_eq!(
+            "amiga".parse::<Profile>(),
+            Err("invalid profile: amiga".to_string())
+        );
+    }
+
+    #[test]
+    fn run_until_idle_self_loop() {
+        let mut chip8 = Chip8::new();
+        // 0x200: V0 = 5, 0x202: V0 += 1, 0x204: goto 0x204
+        chip8.memory[0x200..0x206].copy_from_slice(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x04]);
+
+        assert_eq!(chip8.run_until_idle(1000), Ok(3));
+        assert_eq!(chip8.program_counter, 0x204);
+        assert_eq!(chip8.v[0x00], 6);
+    }
+
+    #[test]
+    fn run_to_next_draw() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_game_from_bytes(&[
+                0x60, 0x05, // V0 = 5
+                0x70, 0x01, // V0 += 1
+                0x81, 0x04, // V1 += V0
+                0xa0, 0x00, // I = 0
+                0xd0, 0x15, // draw
+                0x70, 0x01, // V0 += 1
+                0xd0, 0x15, // draw
+            ])
+            .unwrap();
+
+        assert_eq!(chip8.run_to_next_draw(100), Ok(5));
+        assert_eq!(chip8.state, ChipState::Draw);
+        assert_eq!(chip8.program_counter, 0x20a);
+        assert_eq!(chip8.v[0x0], 6);
+
+        assert_eq!(chip8.run_to_next_draw(100), Ok(2));
+        assert_eq!(chip8.program_counter, 0x20e);
+
+        // the cap, in a loop that never draws
+        chip8.load_game_from_bytes(&[0x12, 0x00]).unwrap();
+        chip8.program_counter = 0x200;
+        assert_eq!(chip8.run_to_next_draw(100), Ok(100));
+    }
+
+    #[test]
+    fn run_to_next_draw_paused() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_game_from_bytes(&[
+                0x60, 0x05, // V0 = 5
+                0xa0, 0x00, // I = 0
+                0xd0, 0x15, // draw
+            ])
+            .unwrap();
+        chip8.add_breakpoint(0x204);
+
+        assert_eq!(chip8.run_to_next_draw(100), Ok(2));
+        assert_eq!(chip8.state, ChipState::Pause);
+        assert_eq!(chip8.program_counter, 0x204);
+
+        // still paused, nothing runs
+        assert_eq!(chip8.run_to_next_draw(100), Ok(0));
+
+        chip8.resume();
+        assert_eq!(chip8.run_to_next_draw(100), Ok(1));
+        assert_eq!(chip8.state, ChipState::Draw);
+    }
+
+    #[test]
+    fn run_until_idle_paused() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        chip8.pause();
+
+        // a paused machine does not move, it must not spin until max_cycles
+        assert_eq!(chip8.run_until_idle(100), Ok(0));
+        assert_eq!(chip8.program_counter, 0x200);
+
+        // the watchdog pauses the loop
+        chip8.resume();
+        chip8.set_watchdog(Some(10));
+        chip8.v[0x0] = 0;
+        chip8.memory[0x200..0x202].copy_from_slice(&[0x60, 0x00]);
+        assert!(chip8.run_until_idle(1000).unwrap() < 1000);
+        assert_eq!(chip8.state, ChipState::Pause);
+    }
+
+    #[test]
+    fn run_until_idle_breakpoint() {
+        let mut chip8 = Chip8::new();
+        // 0x200: V0 += 1, 0x202: goto 0x200
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        chip8.add_breakpoint(0x202);
+
+        assert_eq!(chip8.run_until_idle(100), Ok(1));
+        assert_eq!(chip8.state, ChipState::Pause);
+        assert_eq!(chip8.program_counter, 0x202);
+
+        // the next lap stops at the breakpoint again
+        chip8.resume();
+        assert_eq!(chip8.run_until_idle(100), Ok(2));
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.v[0x0], 2);
+    }
+
+    #[test]
+    fn run_until_idle_max_cycles() {
+        let mut chip8 = Chip8::new();
+        // 0x200: V0 += 1, 0x202: goto 0x200
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+
+        assert_eq!(chip8.run_until_idle(100), Ok(100));
+        assert_eq!(chip8.v[0x00], 50);
+    }
+
+    #[derive(Default)]
+    struct MockDisplay {
+        draws: Vec<Vec<u8>>,
+    }
+
+    impl Display for MockDisplay {
+        fn draw(&mut self, gfx: &[u8], _width: usize, _height: usize) -> Result<(), String> {
+            self.draws.push(gfx.to_vec());
+            Ok(())
+        }
+
+        fn clear(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /** Presses keys following a script, one entry per frame, then quits */
+    struct MockInput {
+        frames: Vec<Option<u8>>,
+    }
+
+    impl Input for MockInput {
+        fn poll(&mut self, keys: &mut Keypad, state: &mut ChipState) {
+            *keys = Keypad::default();
+            match self.frames.pop() {
+                Some(Some(key)) => keys.press(key),
+                Some(None) => {}
+                None => *state = ChipState::Quit,
+            }
+        }
+    }
+
+    #[test]
+    fn run_loop_with_mock_frontend() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_game_from_bytes(&[
+                0xf0, 0x0a, // V0 = key
+                0xf0, 0x29, // I = sprite of V0
+                0xd1, 0x15, // draw it at (0, 0)
+                0x12, 0x06, // loop
+            ])
+            .unwrap();
+        let mut display = MockDisplay::default();
+        // popped from the end: nothing, key 7, nothing
+        let mut input = MockInput {
+            frames: vec![None, Some(0x7), None],
+        };
+
+        assert_eq!(chip8.run_loop(&mut display, &mut input, &mut ()), Ok(()));
+
+        assert_eq!(display.draws.len(), 1);
+        // "7" starts with 0xF0
+        assert_eq!(display.draws[0][..5], [1, 1, 1, 1, 0]);
+        assert_eq!(chip8.v[0x0], 0x7);
+    }
+
+    #[test]
+    fn run_loop_deterministic() {
+        let run = || {
+            let mut chip8 = Chip8::deterministic(42);
+            chip8
+                .load_game_from_bytes(&[
+                    0xc0, 0x0f, // V0 = random digit
+                    0xf0, 0x18, // ST = V0
+                    0xf0, 0x29, // I = sprite of V0
+                    0xd1, 0x15, // draw it at (0, 0)
+                    0x12, 0x00, // loop
+                ])
+                .unwrap();
+            let mut display = MockDisplay::default();
+            let mut input = MockInput {
+                frames: vec![None; 600],
+            };
+
+            chip8.run_loop(&mut display, &mut input, &mut ()).unwrap();
+
+            (
+                chip8.cycles,
+                chip8.v,
+                chip8.sound_timer,
+                chip8.gfx,
+                display.draws,
+            )
+        };
+
+        let first = run();
+        // a frame per poll whatever the wall clock, not as many as were due in real time
+        assert_eq!(first.0, 600 * u64::from(CYCLES_PER_FRAME));
+        assert_eq!(first, run());
+    }
+
+    #[test]
+    fn frame_clock_slices() {
+        let ms = Duration::from_millis;
+        let mut clock = FrameClock::default();
+
+        assert_eq!(clock.advance(ms(10)), 0);
+        assert_eq!(clock.until_next_frame(), FRAME_DURATION - ms(10));
+
+        // 20ms, the remainder carries over
+        assert_eq!(clock.advance(ms(10)), 1);
+        assert_eq!(
+            clock.until_next_frame(),
+            FRAME_DURATION - (ms(20) - FRAME_DURATION)
+        );
+        assert_eq!(clock.advance(ms(50)), 3);
+
+        // a hitch is only partly caught up
+        assert_eq!(clock.advance(ms(1000)), MAX_CATCH_UP_FRAMES);
+        assert_eq!(clock.until_next_frame(), FRAME_DURATION);
+    }
+
+    #[test]
+    fn frame_clock_drives_cycles_and_timers() {
+        let mut chip8 = Chip8::new();
+        chip8.set_clock_hz(1200);
+        // loop
+        chip8.load_game_from_bytes(&[0x12, 0x00]).unwrap();
+        chip8.delay_timer = 60;
+        let mut clock = FrameClock::default();
+
+        // 50ms: 3 frames of 20 instructions, and 3 timer ticks
+        for _ in 0..clock.advance(Duration::from_millis(50)) {
+            chip8.step();
+        }
+
+        assert_eq!(chip8.cycle_count(), 3 * 20);
+        assert_eq!(chip8.delay_timer, 60 - 3);
+    }
+
+    #[test]
+    fn step_runs_a_frame() {
+        let mut chip8 = Chip8::new();
+        // 0x200: V0 += 1, 0x202: goto 0x200
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        chip8.delay_timer = 2;
+
+        chip8.step();
+
+        assert_eq!(chip8.cycle_count(), u64::from(CYCLES_PER_FRAME));
+        assert_eq!(chip8.delay_timer, 1);
+
+        chip8.pause();
+        chip8.step();
+
+        assert_eq!(chip8.cycle_count(), u64::from(CYCLES_PER_FRAME));
+        assert_eq!(chip8.delay_timer, 1);
+    }
+
+    #[test]
+    fn step_vip_timing() {
+        let mut chip8 = Chip8::new();
+        chip8.set_vip_timing(true);
+        // 0x200: V0 = 1 (6 cycles), 0x202: goto 0x200 (12 cycles)
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x60, 0x01, 0x12, 0x00]);
+
+        chip8.step();
+
+        // 3668 / 18 = 203.8, the last instruction overruns the frame by 4 cycles, which are
+        // taken from the next frame
+        assert_eq!(chip8.cycle_count(), 408);
+        assert_eq!(chip8.machine_cycle_count(), 3672);
+
+        chip8.step();
+
+        assert_eq!(chip8.machine_cycle_count(), 2 * 3668 + 8);
+    }
+
+    #[test]
+    fn run_until_idle_error() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x202].copy_from_slice(&[0x51, 0x21]);
+
+        assert_eq!(
+            chip8.run_until_idle(100),
+            Err("Unknown opcode 0x5121 at 0x200".to_string())
+        );
+    }
+
+    #[test]
+    fn cycle_count_and_last_opcode() {
+        let mut chip8 = Chip8::new();
+
+        let program = [
+            0x60, 0x01, // V0 = 1
+            0x61, 0x02, // V1 = 2
+            0x80, 0x14, // V0 += V1
+            0x12, 0x06, // goto 0x206
+        ];
+        chip8.memory[0x200..0x208].copy_from_slice(&program);
+
+        assert_eq!(chip8.cycle_count(), 0);
+
+        for _ in 0..3 {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(chip8.cycle_count(), 3);
+        assert_eq!(chip8.last_opcode(), 0x8014);
+        assert_eq!(chip8.v[0x00], 3);
+
+        for _ in 0..5 {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(chip8.cycle_count(), 8);
+        assert_eq!(chip8.last_opcode(), 0x1206);
+        assert_eq!(chip8.program_counter, 0x206);
+    }
+
+    #[test]
+    fn recent_trace() {
+        let mut chip8 = Chip8::new();
+
+        let program = [
+            0x60, 0x01, // V0 = 1
+            0x22, 0x08, // call 0x208
+            0x12, 0x04, // goto 0x204
+            0x00, 0x00, //
+            0x00, 0xee, // return
+        ];
+        chip8.memory[0x200..0x20a].copy_from_slice(&program);
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.recent_trace(), []);
+
+        chip8.set_trace_enabled(true);
+        for _ in 0..4 {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(
+            chip8.recent_trace(),
+            [
+                (0x202, 0x2208),
+                (0x208, 0x00ee),
+                (0x204, 0x1204),
+                (0x204, 0x1204)
+            ]
+        );
+    }
+
+    #[test]
+    fn recent_trace_keeps_last_entries() {
+        let mut chip8 = Chip8::new();
+        chip8.set_trace_enabled(true);
+
+        // goto 0x200 forever
+        chip8.memory[0x200] = 0x12;
+        chip8.memory[0x201] = 0x00;
+
+        for _ in 0..1000 {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(chip8.recent_trace().len(), 256);
+        assert_eq!(chip8.recent_trace()[255], (0x200, 0x1200));
+        assert_eq!(chip8.trace.capacity(), 512);
+    }
+
+    #[test]
+    fn get_op_code() {
+        let mut chip8 = Chip8::new();
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
         chip8.memory[program_counter] = 0xfa;
         chip8.memory[program_counter + 1] = 0x1e;
 
-        assert_eq!(chip8.get_op_code(), 0xfa1e);
+        assert_eq!(chip8.get_op_code(), 0xfa1e);
+    }
+
+    #[test]
+    fn get_op_code_at_last_byte() {
+        let mut chip8 = Chip8::new();
+        chip8.program_counter = 4095;
+        chip8.memory[4095] = 0x12;
+        chip8.memory[0x000] = 0x34;
+
+        assert_eq!(chip8.get_op_code(), 0x1234);
+    }
+
+    #[test]
+    fn fetch_wraps_around_end_of_memory() {
+        let mut chip8 = Chip8::new();
+        // 0x240: goto 0xFFF, 0xFFF: V0 = 0x55 split around the end, 0x001: V1 = 0x22
+        chip8.memory[0x240] = 0x1f;
+        chip8.memory[0x241] = 0xff;
+        chip8.memory[0xfff] = 0x60;
+        chip8.memory[0x000] = 0x55;
+        chip8.memory[0x001] = 0x61;
+        chip8.memory[0x002] = 0x22;
+        chip8.program_counter = 0x240;
+
+        chip8.execute();
+        assert_eq!(chip8.program_counter, 0xfff);
+
+        chip8.execute();
+
+        assert_eq!(chip8.last_opcode(), 0x6055);
+        assert_eq!(chip8.v[0x00], 0x55);
+        assert_eq!(chip8.program_counter, 0x1001);
+
+        // the next fetch wraps the program counter
+        chip8.execute();
+
+        assert_eq!(chip8.last_opcode(), 0x6122);
+        assert_eq!(chip8.v[0x01], 0x22);
+        assert_eq!(chip8.program_counter, 0x003);
+    }
+
+    #[test]
+    fn execute_logs_trace() {
+        // Fails if another logger is already set, which is fine
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(LevelFilter::Trace);
+        let mut chip8 = Chip8::new();
+        chip8.program_counter = 0x2a4;
+        chip8.memory[0x2a4..0x2a6].copy_from_slice(&[0x6b, 0x3c]);
+
+        chip8.execute();
+
+        let lines = LOGGER.lines.lock().unwrap().clone();
+        assert!(lines.contains(&"0x2a4: 6b3c LD VB, 0x3c".to_string()));
+    }
+
+    #[test]
+    fn op_code_0n_nn_sys_ignored() {
+        let mut chip8 = Chip8::new();
+        chip8.load_game_from_bytes(&[0x01, 0x23]).unwrap();
+        chip8.v[0x3] = 7;
+        let before = chip8.save_state();
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x202);
+        let after = chip8.save_state();
+        assert_eq!(after.v, before.v);
+        assert_eq!(after.i, before.i);
+        assert_eq!(after.stack_pointer, before.stack_pointer);
+        assert_eq!(after.gfx, before.gfx);
+    }
+
+    #[test]
+    fn op_code_00_e0_clear_screen() {
+        let mut chip8 = Chip8::new();
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xe0;
+        chip8.gfx[2] = 1;
+        chip8.gfx[33] = 1;
+        chip8.gfx[444] = 1;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Clear);
+        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.stack, [0; 16]);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_00_e0_clear_screen_presents_blank_frame() {
+        let mut chip8 = Chip8::new();
+        // 0x200: D005, 0x202: clear, 0x204: wait for a key
+        chip8.memory[0x200..0x206].copy_from_slice(&[0xd0, 0x05, 0x00, 0xe0, 0xf0, 0x0a]);
+        chip8.i = FONT_BASE;
+
+        chip8.emulate_cycle();
+        assert!(chip8.take_dirty());
+
+        chip8.emulate_cycle();
+        chip8.emulate_cycle();
+
+        // the frame presented while blocked on FX0A is empty
+        assert_eq!(chip8.state, ChipState::Block);
+        assert!(chip8.take_dirty());
+        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
+    }
+
+    #[test]
+    fn op_code_00_e0_clear_screen_hires() {
+        let mut chip8 = Chip8::new();
+        chip8.set_hires(true);
+
+        assert_eq!(chip8.width, 128);
+        assert_eq!(chip8.height, 64);
+        assert_eq!(chip8.gfx.len(), 8192);
+
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xe0;
+        chip8.gfx[2] = 1;
+        chip8.gfx[4000] = 1;
+        chip8.gfx[8191] = 1;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Clear);
+        assert_eq!(chip8.gfx, [0u8; 128 * 64]);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+
+        chip8.set_hires(false);
+
+        assert_eq!(chip8.width, 64);
+        assert_eq!(chip8.height, 32);
+        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
+    }
+
+    #[test]
+    fn op_code_00_cn_scroll_down() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xc4;
+        // top-left corner and a pixel on row 2
+        chip8.gfx[0] = 1;
+        chip8.gfx[2 * 64 + 10] = 1;
+        // bottom row scrolls off screen
+        chip8.gfx[31 * 64 + 5] = 1;
+
+        chip8.execute();
+
+        let mut gfx_expected = [0u8; 64 * 32];
+        gfx_expected[4 * 64] = 1;
+        gfx_expected[6 * 64 + 10] = 1;
+
+        assert_eq!(chip8.state, ChipState::Draw);
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_00_fb_scroll_right() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xfb;
+        // vertical lines on column 10 and on the right edge
+        for y in 0..32 {
+            chip8.gfx[y * 64 + 10] = 1;
+            chip8.gfx[y * 64 + 62] = 1;
+        }
+
+        chip8.execute();
+
+        let mut gfx_expected = [0u8; 64 * 32];
+        for y in 0..32 {
+            gfx_expected[y * 64 + 14] = 1;
+        }
+
+        assert_eq!(chip8.state, ChipState::Draw);
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_00_fc_scroll_left() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xfc;
+        // vertical lines on the left edge and on column 10
+        for y in 0..32 {
+            chip8.gfx[y * 64 + 1] = 1;
+            chip8.gfx[y * 64 + 10] = 1;
+        }
+
+        chip8.execute();
+
+        let mut gfx_expected = [0u8; 64 * 32];
+        for y in 0..32 {
+            gfx_expected[y * 64 + 6] = 1;
+        }
+
+        assert_eq!(chip8.state, ChipState::Draw);
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_00_fd_exit() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xfd;
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Quit);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+
+        // The machine stays halted
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Quit);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_00_fe_00_ff_lores_hires() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x210;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xff;
+        chip8.memory[program_counter + 2] = 0x00;
+        chip8.memory[program_counter + 3] = 0xfe;
+        chip8.gfx[33] = 1;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Clear);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!((chip8.width, chip8.height), (128, 64));
+        assert_eq!(chip8.gfx, [0u8; 128 * 64]);
+
+        chip8.gfx[4000] = 1;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Clear);
+        assert_eq!(chip8.program_counter, program_counter + 4);
+        assert_eq!((chip8.width, chip8.height), (64, 32));
+        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
+    }
+
+    #[test]
+    fn op_code_00_ee_return_from_subroutine() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x212;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xee;
+
+        chip8.stack[chip8.stack_pointer] = 0x323;
+        chip8.stack_pointer += 1;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x323);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_1n_nn_goto() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x214;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x14;
+        chip8.memory[program_counter + 1] = 0x32;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x432);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_2n_nn_call_subroutine() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x216;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x24;
+        chip8.memory[program_counter + 1] = 0x36;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x436);
+        assert_eq!(chip8.stack_pointer, 1);
+        assert_eq!(chip8.stack[0], 0x218);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_2n_nn_call_subroutine_last_word() {
+        let mut chip8 = Chip8::new();
+        chip8.set_xo_chip(true);
+        chip8.program_counter = 0xfffe;
+        chip8.memory[0xfffe] = 0x23;
+        chip8.memory[0xffff] = 0x00;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x300);
+        assert_eq!(chip8.stack[0], 0x0000);
+    }
+
+    #[test]
+    fn op_code_2n_nn_00_ee_nested_subroutines() {
+        let mut chip8 = Chip8::new();
+
+        // 0x200: call 0x300
+        chip8.memory[0x200] = 0x23;
+        chip8.memory[0x201] = 0x00;
+        // 0x300: call 0x400
+        chip8.memory[0x300] = 0x24;
+        chip8.memory[0x301] = 0x00;
+        // 0x302: return
+        chip8.memory[0x302] = 0x00;
+        chip8.memory[0x303] = 0xee;
+        // 0x400: return
+        chip8.memory[0x400] = 0x00;
+        chip8.memory[0x401] = 0xee;
+
+        chip8.execute();
+        chip8.execute();
+
+        assert_eq!(chip8.program_counter, 0x400);
+        assert_eq!(chip8.stack_pointer, 2);
+        assert_eq!(chip8.stack[..2], [0x202, 0x302]);
+
+        chip8.execute();
+
+        assert_eq!(chip8.program_counter, 0x302);
+        assert_eq!(chip8.stack_pointer, 1);
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.stack_pointer, 0);
+    }
+
+    #[test]
+    fn op_code_2n_nn_call_subroutine_stack_overflow() {
+        let mut chip8 = Chip8::new();
+
+        // Each subroutine calls the next one, 16 levels deep
+        for n in 0..17 {
+            let address = 0x300 + n * 2;
+            chip8.memory[address] = 0x23;
+            chip8.memory[address + 1] = u8::try_from(address + 2 - 0x300).unwrap();
+        }
+        chip8.program_counter = 0x300;
+
+        for _ in 0..16 {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.stack_pointer, 16);
+        assert_eq!(chip8.program_counter, 0x320);
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::StackOverflow);
+        assert_eq!(chip8.stack_pointer, 16);
+        assert_eq!(chip8.program_counter, 0x320);
+
+        // The machine stays halted
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::StackOverflow);
+        assert_eq!(chip8.program_counter, 0x320);
+    }
+
+    #[test]
+    fn op_code_00_ee_return_from_subroutine_stack_underflow() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x212;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x00;
+        chip8.memory[program_counter + 1] = 0xee;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::StackUnderflow);
+        assert_eq!(chip8.program_counter, program_counter);
+        assert_eq!(chip8.stack_pointer, 0);
+    }
+
+    #[test]
+    fn op_code_3x_nn_skip_if_eq_to_nn_true() {
+        let program_counter = 0x218;
+        let mut chip8 = Chip8Builder::new()
+            .reg(0xA, 0x56)
+            .pc(program_counter)
+            .at(program_counter, [0x3a, 0x56])
+            .build();
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 4);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_3x_nn_skip_if_eq_to_nn_false() {
+        let program_counter = 0x220;
+        let mut chip8 = Chip8Builder::new()
+            .reg(0xB, 0x2f)
+            .pc(program_counter)
+            .at(program_counter, [0x3b, 0x1f])
+            .build();
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_4x_nn_skip_if_not_eq_to_nn_true() {
+        let program_counter = 0x222;
+        let mut chip8 = Chip8Builder::new()
+            .reg(0x4, 0x2a)
+            .pc(program_counter)
+            .at(program_counter, [0x44, 0x1a])
+            .build();
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 4);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_4x_nn_skip_if_not_eq_to_nn_false() {
+        let program_counter = 0x224;
+        let mut chip8 = Chip8Builder::new()
+            .reg(0xF, 0x33)
+            .pc(program_counter)
+            .at(program_counter, [0x4f, 0x33])
+            .build();
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_5x_y0_skip_if_vx_eq_to_vy_true() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x224;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x52;
+        chip8.memory[program_counter + 1] = 0xe0;
+        chip8.v[0x02] = 0x33;
+        chip8.v[0x0e] = 0x33;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 4);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_5x_y0_skip_if_vx_eq_to_vy_false() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x224;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x53;
+        chip8.memory[program_counter + 1] = 0xa0;
+        chip8.v[0x03] = 0x55;
+        chip8.v[0x0a] = 0xaa;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+    }
+
+    #[test]
+    fn op_code_5x_yn_invalid_does_not_skip() {
+        // V1 == V2, a 5XY0 would skip
+        let mut chip8 = Chip8Builder::new()
+            .reg(0x1, 0x42)
+            .reg(0x2, 0x42)
+            .pc(0x230)
+            .at(0x230, [0x51, 0x21])
+            .build();
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Error(0x5121));
+        assert_eq!(chip8.program_counter, 0x230);
+    }
+
+    #[test]
+    fn op_code_unknown_pauses() {
+        // V0 = 1, unknown, V0 = 2
+        let mut chip8 = Chip8Builder::new()
+            .pc(0x200)
+            .at(0x200, [0x60, 0x01, 0x51, 0x21, 0x60, 0x02, 0x12, 0x06])
+            .build();
+        chip8.set_pause_on_unknown_opcode(true);
+
+        chip8.step();
+
+        assert_eq!(chip8.state, ChipState::Pause);
+        assert_eq!(chip8.unknown_opcode(), Some((0x202, 0x5121)));
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.v[0x0], 1);
+
+        chip8.skip_current_instruction();
+        chip8.step();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.unknown_opcode(), None);
+        assert_eq!(chip8.program_counter, 0x206);
+        assert_eq!(chip8.v[0x0], 2);
+    }
+
+    #[test]
+    fn op_code_unknown_sets_error_state() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x224;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x50;
+        chip8.memory[program_counter + 1] = 0x01;
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Error(0x5001));
+        assert_eq!(chip8.program_counter, program_counter);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+
+        // The machine stays halted
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Error(0x5001));
+        assert_eq!(chip8.program_counter, program_counter);
+    }
+
+    #[test]
+    fn op_code_6x_nn_set_vx_to_nn() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x224;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x6d;
+        chip8.memory[program_counter + 1] = 0x1a;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x0d], 0x1a);
+    }
+
+    #[test]
+    fn op_code_7x_nn_add_nn_to_vx() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x224;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x7c;
+        chip8.memory[program_counter + 1] = 0x29;
+        chip8.v[0x0c] = 0x3a;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x0c], 0x63);
+    }
+
+    #[test]
+    fn op_code_7x_nn_add_nn_to_vx_overflow() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x226;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x7c;
+        chip8.memory[program_counter + 1] = 0xff;
+        chip8.v[0x0c] = 0x3a;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x0c], 0x39);
+        // carry flag is not changed
+        assert_eq!(chip8.v[0x0f], 0x00);
+    }
+
+    #[test]
+    fn op_code_8x_y0_set_vx_to_vy() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x228;
+        let vx = 0x11;
+        let vy = 0x4d;
+
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x80;
+        chip8.memory[program_counter + 1] = 0x50;
+        chip8.v[0x00] = vx;
+        chip8.v[0x05] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x00], vy);
+    }
+
+    #[test]
+    fn op_code_8x_y1_set_vx_to_vx_or_vy() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x23;
+        let vy = 0x6a;
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x81;
+        chip8.memory[program_counter + 1] = 0x41;
+        chip8.v[0x01] = vx;
+        chip8.v[0x04] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x01], vx | vy);
+    }
+
+    #[test]
+    fn op_code_8x_y2_set_vx_to_vx_and_vy() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x32;
+        let vy = 0xe5;
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x84;
+        chip8.memory[program_counter + 1] = 0x82;
+        chip8.v[0x04] = vx;
+        chip8.v[0x08] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x04], vx & vy);
+    }
+
+    #[test]
+    fn op_code_8x_y3_set_vx_to_vx_xor_vy() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x46;
+        let vy = 0x4f;
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x85;
+        chip8.memory[program_counter + 1] = 0xc3;
+        chip8.v[0x05] = vx;
+        chip8.v[0x0c] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x05], vx ^ vy);
+    }
+
+    #[test]
+    fn op_code_8x_yn_logic_vf_reset_quirk() {
+        // OR, AND and XOR of V1 and V2
+        let program = [0x81, 0x21, 0x81, 0x22, 0x81, 0x23];
+        for quirk in [false, true] {
+            let mut chip8 = Chip8Builder::new()
+                .reg(0x1, 0x0c)
+                .reg(0x2, 0x0a)
+                .pc(0x200)
+                .at(0x200, program)
+                .build();
+            chip8.set_logic_vf_reset_quirk(quirk);
+
+            for _ in 0..3 {
+                chip8.v[0xF] = 0x42;
+                chip8.execute();
+
+                assert_eq!(chip8.v[0xF], if quirk { 0 } else { 0x42 });
+            }
+            // ((0x0c | 0x0a) & 0x0a) ^ 0x0a
+            assert_eq!(chip8.v[0x1], 0x00);
+        }
+    }
+
+    #[test]
+    fn op_code_8x_y4_set_vx_to_vx_plus_vy() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x65;
+        let vy = 0x1d;
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x82;
+        chip8.memory[program_counter + 1] = 0x64;
+        chip8.v[0x02] = vx;
+        chip8.v[0x06] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x02], vx.wrapping_add(vy));
+        assert_eq!(chip8.v[0x0f], 0);
+    }
+
+    #[test]
+    fn op_code_8x_y4_set_vx_to_vx_plus_vy_overflow() {
+        let mut chip8 = Chip8::new();
+        let vx = 0xd1;
+        let vy = 0xe2;
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x83;
+        chip8.memory[program_counter + 1] = 0xd4;
+        chip8.v[0x03] = vx;
+        chip8.v[0x0d] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x03], vx.wrapping_add(vy));
+        assert_eq!(chip8.v[0x0f], 1);
+    }
+
+    #[test]
+    fn op_code_8x_y5_set_vx_to_vx_minus_vy() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x43;
+        let vy = 0xd2;
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x80;
+        chip8.memory[program_counter + 1] = 0x95;
+        chip8.v[0x00] = vx;
+        chip8.v[0x09] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x00], vx.wrapping_sub(vy));
+        assert_eq!(chip8.v[0x0f], 0);
+    }
+
+    #[test]
+    fn op_code_8x_y5_set_vx_to_vx_minus_vy_overflow() {
+        let mut chip8 = Chip8::new();
+        let vx = 0xf3;
+        let vy = 0x32;
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x83;
+        chip8.memory[program_counter + 1] = 0xc5;
+        chip8.v[0x03] = vx;
+        chip8.v[0x0c] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x03], vx.wrapping_sub(vy));
+        assert_eq!(chip8.v[0x0f], 1);
+    }
+
+    #[test]
+    fn op_code_8x_y5_set_vx_to_vx_minus_vy_equal() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x83;
+        chip8.memory[program_counter + 1] = 0xc5;
+        chip8.v[0x03] = 0x42;
+        chip8.v[0x0c] = 0x42;
+
+        chip8.execute();
+
+        assert_eq!(chip8.v[0x03], 0x00);
+        // no borrow
+        assert_eq!(chip8.v[0x0f], 1);
+    }
+
+    #[test]
+    fn overflow_rom_wraps_without_panicking() {
+        let mut chip8 = Chip8::new();
+
+        let program = [
+            0x60, 0xff, // V0 = 0xFF
+            0x70, 0x02, // V0 += 0x02
+            0x61, 0xf0, // V1 = 0xF0
+            0x62, 0x20, // V2 = 0x20
+            0x81, 0x24, // V1 += V2
+            0x63, 0x10, // V3 = 0x10
+            0x64, 0x20, // V4 = 0x20
+            0x83, 0x45, // V3 -= V4
+            0x65, 0x30, // V5 = 0x30
+            0x66, 0x10, // V6 = 0x10
+            0x85, 0x67, // V5 = V6 - V5
+            0x67, 0x81, // V7 = 0x81
+            0x87, 0x7e, // V7 <<= 1
+            0x68, 0xff, // V8 = 0xFF
+            0x6a, 0x02, // VA = 0x02
+            0x88, 0xa4, // V8 += VA
+            0x12, 0x20, // goto 0x220
+        ];
+        chip8.memory[0x200..0x222].copy_from_slice(&program);
+        let mut flags = Vec::new();
+
+        for _ in 0..17 {
+            chip8.emulate_cycle();
+            flags.push(chip8.v[0x0f]);
+        }
+
+        assert_eq!(chip8.program_counter, 0x220);
+        assert_eq!(chip8.v[0x00], 0x01);
+        assert_eq!(chip8.v[0x01], 0x10);
+        assert_eq!(chip8.v[0x03], 0xf0);
+        assert_eq!(chip8.v[0x05], 0xe0);
+        assert_eq!(chip8.v[0x07], 0x02);
+        assert_eq!(chip8.v[0x08], 0x01);
+        // VF after 8XY4 (carry), 8XY5 (borrow), 8XY7 (borrow), 8XYE (bit 7), 8XY4 (carry)
+        assert_eq!(
+            [flags[4], flags[7], flags[10], flags[12], flags[15]],
+            [1, 0, 0, 1, 1]
+        );
+    }
+
+    #[test]
+    fn op_code_8x_y6_shift_right() {
+        let mut chip8 = Chip8::new();
+        let vx = 0xaf;
+
+        let program_counter = 0x232;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x82;
+        chip8.memory[program_counter + 1] = 0xc6;
+        chip8.v[0x02] = vx;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x02], 0x57);
+        assert_eq!(chip8.v[0x0f], 0x01);
+    }
+
+    #[test]
+    fn op_code_8x_y6_shift_right_quirk() {
+        let mut chip8 = Chip8::new();
+        chip8.set_shift_quirk(true);
+
+        let program_counter = 0x232;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x82;
+        chip8.memory[program_counter + 1] = 0xc6;
+        chip8.v[0x02] = 0xaf;
+        chip8.v[0x0c] = 0x42;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.v[0x02], 0x21);
+        assert_eq!(chip8.v[0x0c], 0x42);
+        assert_eq!(chip8.v[0x0f], 0x00);
+    }
+
+    #[test]
+    fn op_code_8x_y7_set_vx_to_vy_minus_vx() {
+        let mut chip8 = Chip8::new();
+        let vx = 0xaf;
+        let vy = 0x32;
+
+        let program_counter = 0x234;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x8a;
+        chip8.memory[program_counter + 1] = 0xc7;
+        chip8.v[0x0a] = vx;
+        chip8.v[0x0c] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x0a], vy.wrapping_sub(vx));
+        assert_eq!(chip8.v[0x0c], vy);
+        assert_eq!(chip8.v[0x0f], 0x00);
+    }
+
+    #[test]
+    fn op_code_8x_y7_set_vx_to_vy_minus_vx_overflow() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x34;
+        let vy = 0xaa;
+
+        let program_counter = 0x236;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x8a;
+        chip8.memory[program_counter + 1] = 0xc7;
+        chip8.v[0x0a] = vx;
+        chip8.v[0x0c] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x0a], vy.wrapping_sub(vx));
+        assert_eq!(chip8.v[0x0c], vy);
+        assert_eq!(chip8.v[0x0f], 0x01);
+    }
+
+    /** Executes `op_code(b)` with V1 = a, and V2 = b, for every pair of bytes, and checks
+     * that V1 and VF (0x42 before the instruction) are `expected(a, b)` */
+    fn check_every_operand(op_code: impl Fn(u8) -> u16, expected: impl Fn(u8, u8) -> (u8, u8)) {
+        let mut chip8 = Chip8::new();
+        for a in 0..=255 {
+            for b in 0..=255 {
+                chip8.memory[0x200..0x202].copy_from_slice(&op_code(b).to_be_bytes());
+                chip8.program_counter = 0x200;
+                chip8.v[0x1] = a;
+                chip8.v[0x2] = b;
+                chip8.v[0xF] = 0x42;
+
+                chip8.execute();
+
+                assert_eq!(
+                    (chip8.v[0x1], chip8.v[0xF]),
+                    expected(a, b),
+                    "{:04X} with {:#04x}, {:#04x}",
+                    op_code(b),
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn op_code_7x_nn_every_operand() {
+        // VF is not affected
+        check_every_operand(
+            |nn| 0x7100 | u16::from(nn),
+            |a, b| (a.wrapping_add(b), 0x42),
+        );
+    }
+
+    #[test]
+    fn op_code_8x_y4_every_operand() {
+        check_every_operand(
+            |_| 0x8124,
+            |a, b| {
+                let (sum, carry) = a.overflowing_add(b);
+                (sum, u8::from(carry))
+            },
+        );
+    }
+
+    #[test]
+    fn op_code_8x_y5_every_operand() {
+        // VF = 1 without a borrow
+        check_every_operand(|_| 0x8125, |a, b| (a.wrapping_sub(b), u8::from(a >= b)));
+    }
+
+    #[test]
+    fn op_code_8x_y7_every_operand() {
+        check_every_operand(|_| 0x8127, |a, b| (b.wrapping_sub(a), u8::from(b >= a)));
+    }
+
+    #[test]
+    fn op_code_8x_ye_shift_left() {
+        let mut chip8 = Chip8::new();
+        let vx = 0xae;
+
+        let program_counter = 0x238;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x85;
+        chip8.memory[program_counter + 1] = 0xce;
+        chip8.v[0x05] = vx;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x05], 0x5c);
+        assert_eq!(chip8.v[0x0f], 0x01);
+    }
+
+    #[test]
+    fn op_code_8x_ye_shift_left_quirk() {
+        let mut chip8 = Chip8::new();
+        chip8.set_shift_quirk(true);
+
+        let program_counter = 0x238;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x85;
+        chip8.memory[program_counter + 1] = 0xce;
+        chip8.v[0x05] = 0xae;
+        chip8.v[0x0c] = 0x41;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.v[0x05], 0x82);
+        assert_eq!(chip8.v[0x0c], 0x41);
+        assert_eq!(chip8.v[0x0f], 0x00);
+    }
+
+    #[test]
+    fn op_code_8f_y4_result_overwrites_vf() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x8f;
+        chip8.memory[program_counter + 1] = 0x14;
+        chip8.v[0x0f] = 0x10;
+        chip8.v[0x01] = 0x20;
+
+        chip8.execute();
+
+        // VF holds the result, not the flag
+        assert_eq!(chip8.v[0x0f], 0x30);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_8f_y5_result_overwrites_vf() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x8f;
+        chip8.memory[program_counter + 1] = 0x15;
+        chip8.v[0x0f] = 0x30;
+        chip8.v[0x01] = 0x10;
+
+        chip8.execute();
+
+        // VF holds the result, not the flag
+        assert_eq!(chip8.v[0x0f], 0x20);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_8f_y6_result_overwrites_vf() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x8f;
+        chip8.memory[program_counter + 1] = 0x16;
+        chip8.v[0x0f] = 0x06;
+
+        chip8.execute();
+
+        // VF holds the result, not the flag
+        assert_eq!(chip8.v[0x0f], 0x03);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_8f_y7_result_overwrites_vf() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x8f;
+        chip8.memory[program_counter + 1] = 0x17;
+        chip8.v[0x0f] = 0x10;
+        chip8.v[0x01] = 0x30;
+
+        chip8.execute();
+
+        // VF holds the result, not the flag
+        assert_eq!(chip8.v[0x0f], 0x20);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_8f_ye_result_overwrites_vf() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x230;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x8f;
+        chip8.memory[program_counter + 1] = 0x1e;
+        chip8.v[0x0f] = 0x41;
+
+        chip8.execute();
+
+        // VF holds the result, not the flag
+        assert_eq!(chip8.v[0x0f], 0x82);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_9x_y0_skip_if_vx_not_eq_vy_true() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x3e;
+        let vy = 0x3e;
+
+        let program_counter = 0x240;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x93;
+        chip8.memory[program_counter + 1] = 0x60;
+        chip8.v[0x03] = vx;
+        chip8.v[0x06] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x03], vx);
+        assert_eq!(chip8.v[0x06], vy);
+    }
+
+    #[test]
+    fn op_code_9x_y0_skip_if_vx_not_eq_vy_false() {
+        let mut chip8 = Chip8::new();
+        let vx = 0x3e;
+        let vy = 0x0a;
+
+        let program_counter = 0x240;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0x93;
+        chip8.memory[program_counter + 1] = 0x60;
+        chip8.v[0x03] = vx;
+        chip8.v[0x06] = vy;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 4);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x03], vx);
+        assert_eq!(chip8.v[0x06], vy);
+    }
+
+    #[test]
+    fn op_code_an_nn_set_i_to_nnn() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x240;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0xa0;
+        chip8.memory[program_counter + 1] = 0x63;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0x063);
+    }
+
+    #[test]
+    fn op_code_bn_nn_goto_nnn_plus_v0() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x240;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0xb2;
+        chip8.memory[program_counter + 1] = 0x63;
+        chip8.v[0x00] = 0x12;
+
+        chip8.execute();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x12 + 0x263);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x00], 0x12);
+    }
+
+    #[test]
+    fn op_code_cx_nn_set_vx_to_rand_and_nn() {
+        let mut chip8 = Chip8::with_seed(42);
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let program_counter = 0x240;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0xc4;
+        chip8.memory[program_counter + 1] = 0x0f;
+
+        chip8.execute();
+
+        assert_eq!(chip8.v[0x04], rng.gen_range(0..=255) & 0x0f);
+        assert_eq!(chip8.program_counter, program_counter + 2);
+
+        // the same seed gives the same numbers
+        let mut other = Chip8::with_seed(42);
+        other.program_counter = program_counter;
+        other.memory[program_counter] = 0xc4;
+        other.memory[program_counter + 1] = 0x0f;
+        other.execute();
+
+        assert_eq!(other.v[0x04], chip8.v[0x04]);
+    }
+
+    #[test]
+    fn op_code_dx_yn_draw_collision() {
+        let mut chip8 = Chip8::new();
+        // 0x200: D015, 0x202: D015
+        chip8.memory[0x200..0x204].copy_from_slice(&[0xd0, 0x15, 0xd0, 0x15]);
+        chip8.v[0x00] = 0x02;
+        chip8.v[0x01] = 0x03;
+        chip8.v[0x0f] = 1;
+        chip8.i = FONT_BASE;
+
+        chip8.execute();
+
+        assert!(!chip8.collision_occurred());
+        assert_eq!(chip8.gfx.iter().filter(|pixel| **pixel == 1).count(), 14);
+
+        chip8.execute();
+
+        assert!(chip8.collision_occurred());
+        assert_eq!(chip8.gfx, vec![0; 64 * 32]);
+    }
+
+    #[test]
+    fn plot_sprite_collision() {
+        let mut chip8 = Chip8::new();
+        chip8.i = FONT_BASE;
+
+        assert!(!chip8.plot_sprite(10, 4, 8, 5));
+        assert_eq!(chip8.gfx[4 * 64 + 10..4 * 64 + 14], [1, 1, 1, 1]);
+        // the "0" next to it does not touch any lit pixel
+        assert!(!chip8.plot_sprite(14, 4, 8, 5));
+
+        // erases the first "0"
+        assert!(chip8.plot_sprite(10, 4, 8, 5));
+        assert_eq!(chip8.gfx[4 * 64 + 10..4 * 64 + 14], [0, 0, 0, 0]);
+        assert_eq!(chip8.v[0x0F], 0);
+    }
+
+    #[test]
+    fn collision_debug_records_erased_pixels() {
+        let mut chip8 = Chip8::new();
+        chip8.set_collision_debug(true);
+        // "0" at (0, 0), then "1" over it
+        chip8
+            .load_game_from_bytes(&[0xd0, 0x05, 0x60, 0x01, 0xf0, 0x29, 0x61, 0x00, 0xd1, 0x15])
+            .unwrap();
+        chip8.i = FONT_BASE;
+
+        for _ in 0..5 {
+            chip8.emulate_cycle();
+        }
+
+        // the top row of "1" meets "0" at x = 2, its bottom row at x = 1 to 3
+        assert_eq!(
+            chip8.collided_pixels(),
+            [2, 4 * 64 + 1, 4 * 64 + 2, 4 * 64 + 3]
+        );
+
+        // kept for one frame
+        chip8.step();
+        assert!(chip8.collided_pixels().is_empty());
+    }
+
+    #[test]
+    fn draw_stats() {
+        let mut chip8 = Chip8::new();
+        // "0" and "1" overlapping at (0, 0), then "1" at (8, 0)
+        chip8
+            .load_game_from_bytes(&[
+                0xd0, 0x05, 0x60, 0x01, 0xf0, 0x29, 0x61, 0x00, 0xd1, 0x15, 0x60, 0x08, 0xd0, 0x15,
+            ])
+            .unwrap();
+        chip8.i = FONT_BASE;
+
+        for _ in 0..7 {
+            chip8.emulate_cycle();
+        }
+
+        // the "1" turns off 4 of the "0" pixels
+        assert_eq!(
+            chip8.draw_stats(),
+            DrawStats {
+                draws: 3,
+                collisions: 1,
+                pixels_flipped: 14 + 8 + 8,
+            }
+        );
+    }
+
+    #[test]
+    fn op_code_dx_yn_collision_read_by_next_skip() {
+        let mut chip8 = Chip8Builder::new()
+            .i(FONT_BASE)
+            .pc(0x200)
+            .at(
+                0x200,
+                [
+                    0xd0, 0x15, // draw a "0"
+                    0x3f, 0x01, // skip if VF == 1
+                    0x62, 0x01, // V2 = 1
+                    0xd0, 0x15, // draw it again, erasing it
+                    0x3f, 0x01, // skip if VF == 1
+                    0x63, 0x01, // V3 = 1
+                    0x12, 0x0c, // loop
+                ],
+            )
+            .build();
+
+        chip8.run_until_idle(100).unwrap();
+
+        assert_eq!(chip8.program_counter, 0x20c);
+        // no collision, no skip
+        assert_eq!(chip8.v[0x2], 1);
+        // collision, skipped
+        assert_eq!(chip8.v[0x3], 0);
+        assert_eq!(chip8.v[0xF], 1);
+    }
+
+    #[test]
+    fn op_code_dx_yn_draw_wraps_start_and_clips() {
+        let mut chip8 = Chip8::new();
+        chip8.program_counter = 0x200;
+        chip8.memory[0x200] = 0xd0;
+        chip8.memory[0x201] = 0x15;
+        // (66, 62) wraps to (2, 30), the sprite is clipped after 2 rows
+        chip8.v[0x00] = 66;
+        chip8.v[0x01] = 62;
+        chip8.i = FONT_BASE;
+
+        chip8.execute();
+
+        let mut gfx_expected = vec![0u8; 64 * 32];
+        gfx_expected[30 * 64 + 2..30 * 64 + 6].fill(1);
+        gfx_expected[31 * 64 + 2] = 1;
+        gfx_expected[31 * 64 + 5] = 1;
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert!(!chip8.collision_occurred());
+    }
+
+    fn draw_at_bottom_right(draw_quirk: DrawQuirk) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_draw_quirk(draw_quirk);
+        chip8.program_counter = 0x200;
+        chip8.memory[0x200] = 0xd0;
+        chip8.memory[0x201] = 0x15;
+        // "0" at (62, 30), over a lit pixel at (0, 2)
+        chip8.v[0x00] = 62;
+        chip8.v[0x01] = 30;
+        chip8.i = FONT_BASE;
+        chip8.gfx[2 * 64] = 1;
+
+        chip8.execute();
+        chip8
+    }
+
+    #[test]
+    fn op_code_dx_yn_draw_wrap_quirk() {
+        let chip8 = draw_at_bottom_right(DrawQuirk {
+            wrap_horizontal: true,
+            wrap_vertical: true,
+        });
+
+        let mut gfx_expected = vec![0u8; 64 * 32];
+        for (y, columns) in [(30, [62, 63, 0, 1]), (2, [62, 63, 0, 1])] {
+            for x in columns {
+                gfx_expected[y * 64 + x] = 1;
+            }
+        }
+        for y in [31, 0, 1] {
+            gfx_expected[y * 64 + 62] = 1;
+            gfx_expected[y * 64 + 1] = 1;
+        }
+        // the pixel at (0, 2) was turned off
+        gfx_expected[2 * 64] = 0;
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert!(chip8.collision_occurred());
+    }
+
+    #[test]
+    fn op_code_dx_yn_draw_wrapped_pixel_collision() {
+        for wrap_horizontal in [true, false] {
+            let mut chip8 = Chip8::new();
+            chip8.set_draw_quirk(DrawQuirk {
+                wrap_horizontal,
+                wrap_vertical: false,
+            });
+            // a full 8 pixel row at x = 62
+            chip8
+                .load_game_from_bytes(&[0xd0, 0x11, 0xff, 0x00])
+                .unwrap();
+            chip8.i = 0x202;
+            chip8.v[0x0] = 62;
+            chip8.gfx[3] = 1;
+
+            chip8.emulate_cycle();
+
+            if wrap_horizontal {
+                // columns 62, 63 and 0 to 5, the pixel at column 3 was turned off
+                assert_eq!(chip8.gfx[62..64], [1, 1]);
+                assert_eq!(chip8.gfx[..8], [1, 1, 1, 0, 1, 1, 0, 0]);
+                assert!(chip8.collision_occurred());
+            } else {
+                assert_eq!(chip8.gfx[62..64], [1, 1]);
+                assert_eq!(chip8.gfx[..8], [0, 0, 0, 1, 0, 0, 0, 0]);
+                assert!(!chip8.collision_occurred());
+            }
+        }
+    }
+
+    #[test]
+    fn op_code_dx_yn_draw_clip() {
+        let chip8 = draw_at_bottom_right(DrawQuirk::default());
+
+        let mut gfx_expected = vec![0u8; 64 * 32];
+        gfx_expected[30 * 64 + 62..30 * 64 + 64].fill(1);
+        gfx_expected[31 * 64 + 62] = 1;
+        gfx_expected[2 * 64] = 1;
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert!(!chip8.collision_occurred());
     }
 
     #[test]
-    fn op_code_00_e0_clear_screen() {
+    fn op_code_dx_yn_draw_wrap_horizontal_only() {
+        let chip8 = draw_at_bottom_right(DrawQuirk {
+            wrap_horizontal: true,
+            wrap_vertical: false,
+        });
+
+        let mut gfx_expected = vec![0u8; 64 * 32];
+        for x in [62, 63, 0, 1] {
+            gfx_expected[30 * 64 + x] = 1;
+        }
+        gfx_expected[31 * 64 + 62] = 1;
+        gfx_expected[31 * 64 + 1] = 1;
+        gfx_expected[2 * 64] = 1;
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert!(!chip8.collision_occurred());
+    }
+
+    #[test]
+    fn op_code_bx_nn_jump_quirk() {
         let mut chip8 = Chip8::new();
-        let program_counter = 0x210;
+        chip8.set_jump_quirk(true);
+
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x00;
-        chip8.memory[program_counter + 1] = 0xe0;
-        chip8.gfx[2] = 1;
-        chip8.gfx[33] = 1;
-        chip8.gfx[444] = 1;
+        chip8.memory[program_counter..program_counter + 2].copy_from_slice(&[0xb3, 0x00]);
+        chip8.v[0x0] = 0x10;
+        chip8.v[0x3] = 0x04;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Clear);
-        assert_eq!(chip8.gfx, [0u8; 64 * 32]);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.stack, [0; 16]);
-        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.program_counter, 0x304);
     }
 
     #[test]
-    fn op_code_00_ee_return_from_subroutine() {
+    fn op_code_fx_55_fx_65_load_store_quirk() {
         let mut chip8 = Chip8::new();
+        chip8.set_load_store_quirk(true);
 
-        let program_counter = 0x212;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x00;
-        chip8.memory[program_counter + 1] = 0xee;
+        chip8.memory[program_counter..program_counter + 4]
+            .copy_from_slice(&[0xf2, 0x55, 0xf1, 0x65]);
+        chip8.v[..3].copy_from_slice(&[0x11, 0x22, 0x33]);
+        chip8.memory[0x303..0x305].copy_from_slice(&[0x44, 0x55]);
+        chip8.i = 0x300;
 
-        chip8.stack[chip8.stack_pointer] = 0x321;
-        chip8.stack_pointer += 1;
+        chip8.execute();
+
+        assert_eq!(chip8.memory[0x300..0x303], [0x11, 0x22, 0x33]);
+        assert_eq!(chip8.i, 0x303);
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, 0x321 + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[..3], [0x44, 0x55, 0x33]);
+        assert_eq!(chip8.i, 0x305);
     }
 
     #[test]
-    fn op_code_1n_nn_goto() {
+    fn op_code_bn_nn_goto_nnn_plus_v0_wraps() {
         let mut chip8 = Chip8::new();
 
-        let program_counter = 0x214;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x14;
-        chip8.memory[program_counter + 1] = 0x32;
+        chip8.memory[program_counter] = 0xbf;
+        chip8.memory[program_counter + 1] = 0xf0;
+        chip8.v[0x00] = 0x20;
 
         chip8.execute();
 
         assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, 0x432);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.program_counter, 0x010);
     }
 
     #[test]
-    fn op_code_2n_nn_call_subroutine() {
+    fn op_code_dx_yn_draw_sets_dirty() {
         let mut chip8 = Chip8::new();
+        // 0x200: V0 = 1, 0x202: D005
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x60, 0x01, 0xd0, 0x05]);
 
-        let program_counter = 0x216;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x24;
-        chip8.memory[program_counter + 1] = 0x36;
+        chip8.emulate_cycle();
 
-        chip8.execute();
+        assert!(!chip8.take_dirty());
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, 0x436);
-        assert_eq!(chip8.stack_pointer, 1);
-        assert_eq!(chip8.stack[0], 0x216);
-        assert_eq!(chip8.i, 0);
+        chip8.emulate_cycle();
+
+        assert!(chip8.take_dirty());
+        // presenting the frame clears the flag
+        assert!(!chip8.take_dirty());
     }
 
     #[test]
-    fn op_code_3x_nn_skip_if_eq_to_nn_true() {
+    fn op_code_fn_01_draw_plane_2() {
         let mut chip8 = Chip8::new();
+        chip8.set_xo_chip(true);
 
-        let program_counter = 0x218;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x3a;
-        chip8.memory[program_counter + 1] = 0x56;
-        chip8.v[0x0a] = 0x56;
+        // F201 selects plane 2, D011 draws a 1 row sprite at (0, 0)
+        chip8.memory[program_counter..program_counter + 4]
+            .copy_from_slice(&[0xf2, 0x01, 0xd0, 0x11]);
+        chip8.i = 0x300;
+        chip8.memory[0x300] = 0b1100_0000;
+        chip8.gfx[1] = 1;
 
+        chip8.execute();
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
+        // plane 1 is untouched, plane 2 has the sprite
+        assert_eq!(chip8.gfx[..3], [2, 3, 0]);
+        assert_eq!(chip8.v[0x0F], 0);
         assert_eq!(chip8.program_counter, program_counter + 4);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
     }
 
     #[test]
-    fn op_code_3x_nn_skip_if_eq_to_nn_false() {
+    fn op_code_dx_yn_draw_both_planes() {
         let mut chip8 = Chip8::new();
+        chip8.selected_plane = 3;
 
-        let program_counter = 0x220;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x3b;
-        chip8.memory[program_counter + 1] = 0x1f;
-        chip8.v[0x0b] = 0x2f;
+        chip8.memory[program_counter..program_counter + 2].copy_from_slice(&[0xd0, 0x11]);
+        // plane 1 sprite, then plane 2 sprite
+        chip8.i = 0x300;
+        chip8.memory[0x300..0x302].copy_from_slice(&[0b1010_0000, 0b1100_0000]);
+        chip8.gfx[1] = 2;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.gfx[..3], [3, 0, 1]);
+        // the plane 2 pixel at x = 1 was turned off
+        assert_eq!(chip8.v[0x0F], 1);
     }
 
     #[test]
-    fn op_code_4x_nn_skip_if_not_eq_to_nn_true() {
+    fn op_code_00_e0_clear_selected_plane() {
         let mut chip8 = Chip8::new();
+        chip8.selected_plane = 2;
 
-        let program_counter = 0x222;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x44;
-        chip8.memory[program_counter + 1] = 0x1a;
-        chip8.v[0x04] = 0x2a;
+        chip8.memory[program_counter..program_counter + 2].copy_from_slice(&[0x00, 0xe0]);
+        chip8.gfx[..3].copy_from_slice(&[1, 2, 3]);
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 4);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.gfx[..3], [1, 0, 1]);
     }
 
     #[test]
-    fn op_code_4x_nn_skip_if_not_eq_to_nn_false() {
+    fn op_code_00_e0_clear_planes_hires() {
         let mut chip8 = Chip8::new();
+        chip8.set_hires(true);
+        chip8.memory[0x240..0x244].copy_from_slice(&[0x00, 0xe0, 0x00, 0xe0]);
+        chip8.program_counter = 0x240;
+        chip8.gfx.fill(3);
 
-        let program_counter = 0x224;
+        // no plane selected: nothing is cleared
+        chip8.selected_plane = 0;
+        chip8.execute();
+        assert!(chip8.gfx.iter().all(|&pixel| pixel == 3));
+
+        chip8.selected_plane = 3;
+        chip8.execute();
+        assert_eq!(chip8.gfx, [0u8; 128 * 64]);
+    }
+
+    #[test]
+    fn op_code_dx_y0_draw_16x16_hires() {
+        let mut chip8 = Chip8::new();
+        chip8.set_hires(true);
+
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x4f;
-        chip8.memory[program_counter + 1] = 0x33;
-        chip8.v[0x0f] = 0x33;
+        chip8.memory[program_counter] = 0xd0;
+        chip8.memory[program_counter + 1] = 0x10;
+        chip8.v[0x00] = 0x08;
+        chip8.v[0x01] = 0x04;
+        chip8.i = 0x300;
+        chip8.memory[0x300..0x320].fill(0xff);
+        // the bottom right pixel is already lit
+        chip8.gfx[19 * 128 + 23] = 1;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
+        let mut gfx_expected = [0u8; 128 * 64];
+        for y in 4..20 {
+            for x in 8..24 {
+                gfx_expected[y * 128 + x] = 1;
+            }
+        }
+        gfx_expected[19 * 128 + 23] = 0;
+
+        assert_eq!(chip8.state, ChipState::Draw);
         assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.gfx, gfx_expected);
+        assert_eq!(chip8.v[0x0f], 1);
     }
 
     #[test]
-    fn op_code_5x_y0_skip_if_vx_eq_to_vy_true() {
+    fn op_code_dx_yn_display_wait_quirk() {
         let mut chip8 = Chip8::new();
+        chip8.set_display_wait_quirk(true);
+        // 0x200: D005, 0x202: goto 0x200
+        chip8.memory[0x200..0x204].copy_from_slice(&[0xd0, 0x05, 0x12, 0x00]);
 
-        let program_counter = 0x224;
+        for _ in 0..CYCLES_PER_FRAME {
+            chip8.emulate_cycle();
+        }
+
+        // the first DXYN stalls the rest of the frame
+        assert_eq!(chip8.cycle_count(), 1);
+        assert_eq!(chip8.program_counter, 0x202);
+
+        chip8.tick_timers();
+        for _ in 0..CYCLES_PER_FRAME {
+            chip8.emulate_cycle();
+        }
+
+        // goto, then the second DXYN
+        assert_eq!(chip8.cycle_count(), 3);
+        assert_eq!(chip8.program_counter, 0x202);
+
+        chip8.set_display_wait_quirk(false);
+        chip8.tick_timers();
+        for _ in 0..CYCLES_PER_FRAME {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(chip8.cycle_count(), 3 + u64::from(CYCLES_PER_FRAME));
+    }
+
+    #[test]
+    fn is_beeping_while_sound_timer_runs() {
+        let mut chip8 = Chip8::new();
+        // ST = V0 = 2
+        chip8
+            .load_game_from_bytes(&[0x60, 0x02, 0xf0, 0x18])
+            .unwrap();
+        assert!(!chip8.is_beeping());
+
+        chip8.emulate_cycle();
+        chip8.emulate_cycle();
+        assert!(chip8.is_beeping());
+
+        chip8.tick_timers();
+        assert!(chip8.is_beeping());
+        chip8.tick_timers();
+        assert!(!chip8.is_beeping());
+    }
+
+    #[test]
+    fn op_code_fx_07_reads_ticked_delay_timer() {
+        let mut chip8 = Chip8::new();
+        // V1 = 10, DT = V1, then V2 = DT in a loop
+        chip8
+            .load_game_from_bytes(&[0x61, 0x0a, 0xf1, 0x15, 0xf2, 0x07, 0x12, 0x04])
+            .unwrap();
+        chip8.emulate_cycle();
+        chip8.emulate_cycle();
+
+        for _ in 0..3 {
+            chip8.tick_timers();
+        }
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.v[0x2], 7);
+
+        // a frame reads the timer before the frame tick decrements it
+        chip8.step();
+
+        assert_eq!(chip8.v[0x2], 7);
+
+        chip8.step();
+
+        assert_eq!(chip8.v[0x2], 6);
+    }
+
+    #[test]
+    fn op_code_ex_9e_skip_if_key_pressed() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x52;
-        chip8.memory[program_counter + 1] = 0xe0;
-        chip8.v[0x02] = 0x33;
-        chip8.v[0x0e] = 0x33;
+        chip8.memory[program_counter] = 0xe5;
+        chip8.memory[program_counter + 1] = 0x9e;
+        chip8.v[0x05] = 0x0b;
 
         chip8.execute();
+        assert_eq!(chip8.program_counter, program_counter + 2);
 
-        assert_eq!(chip8.state, ChipState::Run);
+        chip8.keys.press(0x0b);
+        chip8.program_counter = program_counter;
+        chip8.execute();
         assert_eq!(chip8.program_counter, program_counter + 4);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
+
+        chip8.keys.release(0x0b);
+        chip8.program_counter = program_counter;
+        chip8.execute();
+        assert_eq!(chip8.program_counter, program_counter + 2);
     }
 
     #[test]
-    fn op_code_5x_y0_skip_if_vx_eq_to_vy_false() {
+    fn op_code_ex_a1_skip_if_not_key_pressed() {
         let mut chip8 = Chip8::new();
 
-        let program_counter = 0x224;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x53;
-        chip8.memory[program_counter + 1] = 0xa0;
-        chip8.v[0x03] = 0x55;
-        chip8.v[0x0a] = 0xaa;
+        chip8.memory[program_counter] = 0xe5;
+        chip8.memory[program_counter + 1] = 0xa1;
+        chip8.v[0x05] = 0x0b;
+
+        chip8.execute();
+        assert_eq!(chip8.program_counter, program_counter + 4);
 
+        chip8.keys.press(0x0b);
+        chip8.program_counter = program_counter;
         chip8.execute();
+        assert_eq!(chip8.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn op_code_fx_0a_wait_for_key() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x250;
+        chip8.program_counter = program_counter;
+        chip8.memory[program_counter] = 0xf3;
+        chip8.memory[program_counter + 1] = 0x0a;
+        chip8.memory[program_counter + 2] = 0x60;
+        chip8.memory[program_counter + 3] = 0x01;
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Block);
+        assert_eq!(chip8.program_counter, program_counter);
+        assert_eq!(chip8.v[0x03], 0);
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Block);
+        assert_eq!(chip8.program_counter, program_counter);
+        assert_eq!(chip8.v[0x03], 0);
+
+        chip8.keys.press(0x07);
+        chip8.emulate_cycle();
 
         assert_eq!(chip8.state, ChipState::Run);
         assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.v[0x03], 0x07);
+
+        // the next instruction runs, FX0A is not executed again
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.program_counter, program_counter + 4);
+        assert_eq!(chip8.v[0x00], 0x01);
     }
 
     #[test]
-    fn op_code_6x_nn_set_vx_to_nn() {
+    fn op_code_fx_0a_wait_for_key_ignores_held_key() {
         let mut chip8 = Chip8::new();
 
-        let program_counter = 0x224;
+        let program_counter = 0x250;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x6d;
-        chip8.memory[program_counter + 1] = 0x1a;
+        chip8.memory[program_counter] = 0xf3;
+        chip8.memory[program_counter + 1] = 0x0a;
+        chip8.keys.press(0x02);
 
-        chip8.execute();
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Block);
+        assert_eq!(chip8.program_counter, program_counter);
+
+        // key 2 is released and pressed again
+        chip8.keys.release(0x02);
+        chip8.emulate_cycle();
+        chip8.keys.press(0x02);
+        chip8.emulate_cycle();
 
         assert_eq!(chip8.state, ChipState::Run);
         assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x0d], 0x1a);
+        assert_eq!(chip8.v[0x03], 0x02);
     }
 
     #[test]
-    fn op_code_7x_nn_add_nn_to_vx() {
+    fn op_code_fx_0a_takes_queued_key() {
+        let mut chip8 = Chip8Builder::new()
+            .pc(0x250)
+            .at(0x250, [0xf3, 0x0a])
+            .build();
+        // pressed before the wait, ignored
+        chip8.press_key(0x4);
+        chip8.release_key(0x4);
+
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Block);
+
+        // key 7 is tapped between two cycles, then the lower key 2 is held
+        chip8.press_key(0x7);
+        chip8.release_key(0x7);
+        chip8.press_key(0x2);
+        chip8.emulate_cycle();
+
+        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.program_counter, 0x252);
+        assert_eq!(chip8.v[0x3], 0x7);
+    }
+
+    #[test]
+    fn opcode_coverage() {
         let mut chip8 = Chip8::new();
+        // V0 = 3, loop: V0 -= 1 (V1 = 0xFF) until it is 0, then CLS and stop
+        chip8
+            .load_game_from_bytes(&[
+                0x60, 0x03, 0x61, 0xff, 0x80, 0x14, 0x30, 0x00, 0x12, 0x04, 0x00, 0xe0, 0x00, 0xfd,
+            ])
+            .unwrap();
+
+        chip8.run_until_idle(100).unwrap();
+
+        assert_eq!(
+            chip8.opcode_coverage().iter().copied().collect::<Vec<_>>(),
+            ["00E0", "00FD", "1NNN", "3XNN", "6XNN", "8XY4"]
+        );
+    }
 
-        let program_counter = 0x224;
+    #[test]
+    fn replay_key_events() {
+        let mut chip8 = Chip8::new();
+        // wait for a key in V3, then V0 = 1 and loop
+        chip8
+            .load_game_from_bytes(&[0xf3, 0x0a, 0x60, 0x01, 0x12, 0x04])
+            .unwrap();
+        chip8.replay([
+            KeyEvent {
+                cycle: 12,
+                key: 0x7,
+                pressed: false,
+            },
+            KeyEvent {
+                cycle: 10,
+                key: 0x7,
+                pressed: true,
+            },
+        ]);
+
+        for _ in 0..10 {
+            chip8.emulate_cycle();
+        }
+        assert_eq!(chip8.state, ChipState::Block);
+        assert_eq!(chip8.program_counter, 0x200);
+
+        for _ in 0..10 {
+            chip8.emulate_cycle();
+        }
+        assert_eq!(chip8.v[0x3], 0x7);
+        assert_eq!(chip8.v[0x0], 1);
+        assert_eq!(chip8.program_counter, 0x204);
+        assert!(!chip8.keys.is_pressed(0x7));
+    }
+
+    #[test]
+    fn step_latches_keys_for_skips() {
+        // V0 counts the EX9E that see key V1 up
+        let mut chip8 = Chip8Builder::new()
+            .reg(0x1, 0x5)
+            .pc(0x200)
+            .at(0x200, [0xe1, 0x9e, 0x70, 0x01, 0x12, 0x00])
+            .build();
+        chip8.set_key(0x5, true);
+        // released in the middle of the frame
+        chip8.replay([KeyEvent {
+            cycle: 4,
+            key: 0x5,
+            pressed: false,
+        }]);
+
+        chip8.step();
+
+        assert!(!chip8.keys.is_pressed(0x5));
+        assert_eq!(chip8.v[0x0], 0);
+
+        // the release is seen from the next frame
+        chip8.step();
+
+        assert!(chip8.v[0x0] > 0);
+    }
+
+    #[test]
+    fn op_code_fx_29_set_i_to_sprite() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x7c;
+        chip8.memory[program_counter] = 0xf3;
         chip8.memory[program_counter + 1] = 0x29;
-        chip8.v[0x0c] = 0x3a;
+        chip8.v[0x03] = 0x0a;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.i, FONT_BASE + 50);
+        assert_eq!(
+            chip8.memory[chip8.i..chip8.i + 5],
+            [0xF0, 0x90, 0xF0, 0x90, 0x90]
+        );
         assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x0c], 0x3a + 0x29);
     }
 
     #[test]
-    fn op_code_7x_nn_add_nn_to_vx_overflow() {
+    fn op_code_fx_29_fx_30_low_nibble() {
         let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x204].copy_from_slice(&[0xf3, 0x29, 0xf3, 0x30]);
+        chip8.v[0x03] = 0x1a;
 
-        let program_counter = 0x226;
+        chip8.execute();
+        assert_eq!(chip8.i, FONT_BASE + 50);
+
+        chip8.v[0x03] = 0x13;
+        chip8.execute();
+        assert_eq!(chip8.i, BIGFONT_BASE + 30);
+    }
+
+    #[test]
+    fn op_code_fx_30_set_i_to_big_sprite() {
+        let mut chip8 = Chip8::new();
+
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x7c;
-        chip8.memory[program_counter + 1] = 0xff;
-        chip8.v[0x0c] = 0x3a;
+        chip8.memory[program_counter] = 0xf5;
+        chip8.memory[program_counter + 1] = 0x30;
+        chip8.v[0x05] = 0x03;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
+        assert_eq!(chip8.i, BIGFONT_BASE + 30);
+        assert_eq!(
+            chip8.memory[chip8.i..chip8.i + 10],
+            [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C]
+        );
         assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
+    }
+
+    #[test]
+    fn emulate_cycle_transitions() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_game_from_bytes(&[
+                0x60, 0x05, // V0 = 5
+                0xd0, 0x05, // draw
+                0xd0, 0x05, // draw again
+                0x00, 0xfd, // exit
+            ])
+            .unwrap();
+
+        let transitions: Vec<Transition> = (0..5).map(|_| chip8.emulate_cycle()).collect();
+
+        let changes: Vec<ChipState> = transitions
+            .iter()
+            .filter(|transition| transition.changed())
+            .map(|transition| transition.to)
+            .collect();
+        assert_eq!(changes, [ChipState::Draw, ChipState::Quit]);
+        assert_eq!(
+            transitions[2],
+            Transition {
+                from: ChipState::Draw,
+                to: ChipState::Draw
+            }
+        );
+        // a halted machine stays where it is
+        assert!(!transitions[4].changed());
+    }
+
+    #[test]
+    fn undo_last_instructions() {
+        let mut chip8 = Chip8::new();
+        chip8.set_undo_enabled(true);
+        chip8
+            .load_game_from_bytes(&[
+                0x60, 0xff, // V0 = 0xFF
+                0x61, 0x02, // V1 = 0x02
+                0x80, 0x14, // V0 += V1, VF = 1
+                0xa3, 0x00, // I = 0x300
+                0xf1, 0x55, // [0x300] = V0, V1
+                0xd0, 0x11, // draw [0x300] at (V0, V1)
+            ])
+            .unwrap();
+        for _ in 0..6 {
+            chip8.emulate_cycle();
+        }
+        assert_eq!(chip8.memory[0x300..0x302], [0x01, 0x02]);
+        assert_eq!(chip8.gfx[2 * 64 + 8], 1);
+
+        assert!(chip8.undo_last());
+        assert_eq!(chip8.gfx, vec![0; 64 * 32]);
+        assert_eq!(chip8.program_counter, 0x20a);
+
+        assert!(chip8.undo_last());
+        assert_eq!(chip8.memory[0x300..0x302], [0x00, 0x00]);
+
+        assert!(chip8.undo_last());
         assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x0c], 0x3a + 0xff);
-        // carry flag is not changed
-        assert_eq!(chip8.v[0x0f], 0x00);
+
+        assert!(chip8.undo_last());
+        assert_eq!(chip8.v[0x0], 0xff);
+        assert_eq!(chip8.v[0xF], 0);
+
+        assert!(chip8.undo_last());
+        assert!(chip8.undo_last());
+        assert_eq!(chip8.v, [0; 16]);
+        assert_eq!(chip8.program_counter, 0x200);
+        assert_eq!(chip8.cycle_count(), 0);
+        assert!(!chip8.undo_last());
+    }
+
+    #[test]
+    fn undo_log_is_bounded() {
+        let mut chip8 = Chip8::new();
+        chip8.set_undo_enabled(true);
+        // 7001: V0 += 1, filling memory from 0x200
+        for address in (0x200..0x400).step_by(2) {
+            chip8.memory[address..address + 2].copy_from_slice(&[0x70, 0x01]);
+        }
+
+        for _ in 0..UNDO_LENGTH + 10 {
+            chip8.emulate_cycle();
+        }
+        while chip8.undo_last() {}
+
+        assert_eq!(chip8.v[0x0], 10);
+    }
+
+    #[test]
+    fn code_watch_records_rom_writes() {
+        let mut chip8 = Chip8::new();
+        chip8.set_code_watch(true);
+        chip8
+            .load_game_from_bytes(&[
+                0xa2, 0x08, // I = 0x208
+                0xf1, 0x55, // [0x208] = V0, V1
+                0xa3, 0x00, // I = 0x300, past the ROM
+                0xf1, 0x55, // [0x300] = V0, V1
+                0x00, 0x00, // overwritten
+            ])
+            .unwrap();
+
+        for _ in 0..4 {
+            chip8.emulate_cycle();
+        }
+
+        assert_eq!(chip8.code_writes(), [0x208, 0x209]);
     }
 
     #[test]
-    fn op_code_8x_y0_set_vx_to_vy() {
+    fn decode_cache_invalidated_by_write() {
         let mut chip8 = Chip8::new();
+        chip8.set_decode_cache(true);
+        chip8
+            .load_game_from_bytes(&[
+                0x22, 0x0c, // call 0x20C
+                0x60, 0x72, // V0 = 0x72
+                0x61, 0x01, // V1 = 0x01
+                0xa2, 0x0c, // I = 0x20C
+                0xf1, 0x55, // rewrite 0x20C to V2 += 1
+                0x22, 0x0c, // call 0x20C
+                0x72, 0x05, // 0x20C: V2 += 5
+                0x00, 0xee, // return
+            ])
+            .unwrap();
+
+        for _ in 0..9 {
+            chip8.emulate_cycle();
+        }
 
-        let program_counter = 0x228;
-        let vx = 0x11;
-        let vy = 0x4d;
-
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x80;
-        chip8.memory[program_counter + 1] = 0x50;
-        chip8.v[0x00] = vx;
-        chip8.v[0x05] = vy;
+        assert_eq!(chip8.v[0x2], 5 + 1);
+        assert_eq!(chip8.last_opcode(), 0x7201);
+    }
 
-        chip8.execute();
+    #[test]
+    fn protect_reserved_memory() {
+        let mut chip8 = Chip8::new();
+        chip8.set_protect_reserved(true);
+        // I = font, [I] = V0 - V2 (= 0)
+        chip8
+            .load_game_from_bytes(&[0xa0, 0x50, 0xf2, 0x55, 0xa2, 0x00, 0xf2, 0x55])
+            .unwrap();
+
+        for _ in 0..4 {
+            chip8.emulate_cycle();
+        }
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x00], vy);
+        assert_eq!(chip8.memory[FONT_BASE..FONT_BASE + 80], CHIP8_FONT_SET);
+        // writes from 0x200 still go through
+        assert_eq!(chip8.memory[0x200..0x203], [0, 0, 0]);
     }
 
     #[test]
-    fn op_code_8x_y1_set_vx_to_vx_or_vy() {
+    fn dump_memory_after_fx_55() {
         let mut chip8 = Chip8::new();
-        let vx = 0x23;
-        let vy = 0x6a;
 
-        let program_counter = 0x230;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x81;
-        chip8.memory[program_counter + 1] = 0x41;
-        chip8.v[0x01] = vx;
-        chip8.v[0x04] = vy;
+        chip8.memory[program_counter] = 0xf3;
+        chip8.memory[program_counter + 1] = 0x55;
+        chip8.v[..5].copy_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55]);
+        chip8.i = 0x300;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x01], vx | vy);
+        let memory = chip8.dump_memory();
+        assert_eq!(memory[0x300..0x305], [0x11, 0x22, 0x33, 0x44, 0x00]);
+        assert_eq!(memory[program_counter], 0xf3);
     }
 
     #[test]
-    fn op_code_8x_y2_set_vx_to_vx_and_vy() {
+    fn op_code_fx_75_fx_85_flags_round_trip() {
         let mut chip8 = Chip8::new();
-        let vx = 0x32;
-        let vy = 0xe5;
 
-        let program_counter = 0x230;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x84;
-        chip8.memory[program_counter + 1] = 0x82;
-        chip8.v[0x04] = vx;
-        chip8.v[0x08] = vy;
+        // F275 saves V0 - V2, F285 restores them
+        chip8.memory[program_counter..program_counter + 4]
+            .copy_from_slice(&[0xf2, 0x75, 0xf2, 0x85]);
+        chip8.v[..4].copy_from_slice(&[0x11, 0x22, 0x33, 0x44]);
 
+        chip8.execute();
+        chip8.v = [0; 16];
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x04], vx & vy);
+        assert_eq!(chip8.v[..4], [0x11, 0x22, 0x33, 0x00]);
+        assert_eq!(chip8.program_counter, program_counter + 4);
     }
 
     #[test]
-    fn op_code_8x_y3_set_vx_to_vx_xor_vy() {
+    fn flags_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("chip8-flags-{}", std::process::id()));
         let mut chip8 = Chip8::new();
-        let vx = 0x46;
-        let vy = 0x4f;
+        chip8.v[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        chip8.save_flags_vx(0xF);
 
-        let program_counter = 0x230;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x85;
-        chip8.memory[program_counter + 1] = 0xc3;
-        chip8.v[0x05] = vx;
-        chip8.v[0x0c] = vy;
+        chip8.save_flags(&path).unwrap();
+        let mut restored = Chip8::new();
+        restored.load_flags(&path).unwrap();
+        fs::remove_file(&path).unwrap();
 
-        chip8.execute();
+        assert_eq!(restored.flags, [1, 2, 3, 4, 5, 6, 7, 8]);
+        // a missing file leaves the flags cleared
+        assert_eq!(Chip8::new().load_flags(&path), Ok(()));
+    }
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x05], vx ^ vy);
+    #[test]
+    fn dump_memory_to_unwritable_path() {
+        let chip8 = Chip8::new();
+
+        let result = chip8.dump_memory_to_file(Path::new("/nonexistent/memory.bin"));
+
+        assert!(result.is_err());
     }
 
     #[test]
-    fn op_code_8x_y4_set_vx_to_vx_plus_vy() {
+    fn poke_and_peek() {
         let mut chip8 = Chip8::new();
-        let vx = 0x65;
-        let vy = 0x1d;
+        let before = chip8.dump_memory().to_vec();
 
-        let program_counter = 0x230;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x82;
-        chip8.memory[program_counter + 1] = 0x64;
-        chip8.v[0x02] = vx;
-        chip8.v[0x06] = vy;
+        assert_eq!(chip8.poke(0x300, 0xab), Ok(()));
 
-        chip8.execute();
+        assert_eq!(chip8.peek(0x300), Some(0xab));
+        let mut expected = before;
+        expected[0x300] = 0xab;
+        assert_eq!(chip8.dump_memory(), expected);
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x02], vx + vy);
-        assert_eq!(chip8.v[0x0f], 0);
+        assert_eq!(
+            chip8.poke(0x1000, 0xab),
+            Err("address 0x1000 is past the end of memory (0x1000)".to_string())
+        );
+        assert_eq!(chip8.peek(0x1000), None);
     }
 
     #[test]
-    fn op_code_8x_y4_set_vx_to_vx_plus_vy_overflow() {
+    fn op_code_f0_00_long_load_i() {
         let mut chip8 = Chip8::new();
-        let vx = 0xd1;
-        let vy = 0xe2;
+        chip8.set_xo_chip(true);
 
-        let program_counter = 0x230;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x83;
-        chip8.memory[program_counter + 1] = 0xd4;
-        chip8.v[0x03] = vx;
-        chip8.v[0x0d] = vy;
+        chip8.memory[program_counter..program_counter + 4]
+            .copy_from_slice(&[0xf0, 0x00, 0xa1, 0x23]);
 
         chip8.execute();
 
         assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x03], vx + vy);
-        assert_eq!(chip8.v[0x0f], 1);
+        assert_eq!(chip8.i, 0xa123);
+        assert_eq!(chip8.program_counter, program_counter + 4);
     }
 
     #[test]
-    fn op_code_8x_y5_set_vx_to_vx_minus_vy() {
-        let mut chip8 = Chip8::new();
-        let vx = 0x43;
-        let vy = 0xd2;
-
-        let program_counter = 0x230;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x80;
-        chip8.memory[program_counter + 1] = 0x95;
-        chip8.v[0x00] = vx;
-        chip8.v[0x09] = vy;
+    fn op_code_f0_02_load_audio_pattern() {
+        let pattern: Vec<u8> = (0..16).map(|n| n * 0x11).collect();
+        let mut chip8 = Chip8Builder::new()
+            .i(0x300)
+            .pc(0x200)
+            .at(0x200, [0xf0, 0x02])
+            .at(0x300, &pattern)
+            .build();
+        assert_eq!(chip8.audio_pattern(), None);
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x00], vx - vy);
-        assert_eq!(chip8.v[0x0f], 0);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.audio_pattern().map(Vec::from), Some(pattern));
+        // I is not moved
+        assert_eq!(chip8.i, 0x300);
     }
 
     #[test]
-    fn op_code_8x_y5_set_vx_to_vx_minus_vy_overflow() {
-        let mut chip8 = Chip8::new();
-        let vx = 0xf3;
-        let vy = 0x32;
-
-        let program_counter = 0x230;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x83;
-        chip8.memory[program_counter + 1] = 0xc5;
-        chip8.v[0x03] = vx;
-        chip8.v[0x0c] = vy;
+    fn op_code_fx_3a_set_pitch() {
+        let mut chip8 = Chip8Builder::new()
+            .reg(0x7, 112)
+            .pc(0x200)
+            .at(0x200, [0xf7, 0x3a])
+            .build();
+        assert_eq!(chip8.pattern_rate(), 4000.0);
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x03], vx - vy);
-        assert_eq!(chip8.v[0x0f], 1);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.pitch, 112);
+        // an octave higher every 48
+        assert_eq!(chip8.pattern_rate(), 8000.0);
     }
 
     #[test]
-    fn op_code_8x_y6_shift_right() {
+    fn op_code_f0_00_long_load_i_needs_xo_chip() {
         let mut chip8 = Chip8::new();
-        let vx = 0xaf;
 
-        let program_counter = 0x232;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x82;
-        chip8.memory[program_counter + 1] = 0xc6;
-        chip8.v[0x02] = vx;
+        chip8.memory[program_counter..program_counter + 4]
+            .copy_from_slice(&[0xf0, 0x00, 0xa1, 0x23]);
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x02], 0x57);
-        assert_eq!(chip8.v[0x0f], 0x01);
+        assert_eq!(chip8.state, ChipState::Error(0xf000));
+        assert_eq!(chip8.program_counter, program_counter);
     }
 
     #[test]
-    fn op_code_8x_y7_set_vx_to_vy_minus_vx() {
+    fn xo_chip_memory_beyond_4k() {
         let mut chip8 = Chip8::new();
-        let vx = 0xaf;
-        let vy = 0x32;
+        chip8.set_xo_chip(true);
 
-        let program_counter = 0x234;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x8a;
-        chip8.memory[program_counter + 1] = 0xc7;
-        chip8.v[0x0a] = vx;
-        chip8.v[0x0c] = vy;
+        // F155 stores V0 - V1 at 0x1000, past the 4K boundary, then F01E moves I
+        // further without wrapping to 4K
+        chip8.memory[program_counter..program_counter + 4]
+            .copy_from_slice(&[0xf1, 0x55, 0xf0, 0x1e]);
+        chip8.i = 0x1000;
+        chip8.v[0x00] = 0xab;
+        chip8.v[0x01] = 0xcd;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x0a], vy - vx);
-        assert_eq!(chip8.v[0x0c], vy);
-        assert_eq!(chip8.v[0x0f], 0x00);
+        assert_eq!(chip8.dump_memory().len(), 65536);
+        assert_eq!(chip8.memory[0x1000..0x1002], [0xab, 0xcd]);
+
+        chip8.execute();
+
+        assert_eq!(chip8.i, 0x1000 + 0xab);
+        assert_eq!(chip8.program_counter, program_counter + 4);
     }
 
     #[test]
-    fn op_code_8x_y7_set_vx_to_vy_minus_vx_overflow() {
+    fn framebuffer_packed_font_zero() {
         let mut chip8 = Chip8::new();
-        let vx = 0x34;
-        let vy = 0xaa;
 
-        let program_counter = 0x236;
+        let program_counter = 0x240;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x8a;
-        chip8.memory[program_counter + 1] = 0xc7;
-        chip8.v[0x0a] = vx;
-        chip8.v[0x0c] = vy;
+        // draw the "0" glyph at (8, 1)
+        chip8.memory[program_counter] = 0xd0;
+        chip8.memory[program_counter + 1] = 0x15;
+        chip8.v[0x00] = 0x08;
+        chip8.v[0x01] = 0x01;
+        chip8.i = FONT_BASE;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x0a], vy - vx);
-        assert_eq!(chip8.v[0x0c], vy);
-        assert_eq!(chip8.v[0x0f], 0x01);
+        let mut packed_expected = [0u8; 256];
+        packed_expected[8 + 1] = 0xf0;
+        packed_expected[16 + 1] = 0x90;
+        packed_expected[24 + 1] = 0x90;
+        packed_expected[32 + 1] = 0x90;
+        packed_expected[40 + 1] = 0xf0;
+
+        assert_eq!(chip8.framebuffer_packed(), packed_expected);
     }
 
     #[test]
-    fn op_code_8x_ye_shift_left() {
+    fn op_code_fx_1e_add_vx_to_i_wraps() {
         let mut chip8 = Chip8::new();
-        let vx = 0xae;
 
-        let program_counter = 0x238;
+        let program_counter = 0x260;
         chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x85;
-        chip8.memory[program_counter + 1] = 0xce;
-        chip8.v[0x05] = vx;
+        chip8.memory[program_counter] = 0xf4;
+        chip8.memory[program_counter + 1] = 0x1e;
+        chip8.v[0x04] = 0xf0;
+        chip8.i = 0xffe;
 
         chip8.execute();
 
         assert_eq!(chip8.state, ChipState::Run);
         assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x05], 0x5c);
-        assert_eq!(chip8.v[0x0f], 0x01);
+        assert_eq!(chip8.i, 0x0ee);
+        // VF is not affected
+        assert_eq!(chip8.v[0x0f], 0);
     }
 
     #[test]
-    fn op_code_9x_y0_skip_if_vx_not_eq_vy_true() {
-        let mut chip8 = Chip8::new();
-        let vx = 0x3e;
-        let vy = 0x3e;
+    fn op_code_fx_1e_add_vx_to_i_overflow_quirk() {
+        let mut chip8 = Chip8Builder::new()
+            .reg(0x4, 0x20)
+            .i(0xff0)
+            .pc(0x260)
+            .at(0x260, [0xf4, 0x1e, 0xf4, 0x1e])
+            .build();
+        chip8.set_i_overflow_quirk(true);
 
-        let program_counter = 0x240;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x93;
-        chip8.memory[program_counter + 1] = 0x60;
-        chip8.v[0x03] = vx;
-        chip8.v[0x06] = vy;
+        chip8.execute();
+
+        assert_eq!(chip8.i, 0x010);
+        assert_eq!(chip8.v[0x0f], 1);
 
+        // no overflow clears VF
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x03], vx);
-        assert_eq!(chip8.v[0x06], vy);
+        assert_eq!(chip8.i, 0x030);
+        assert_eq!(chip8.v[0x0f], 0);
     }
 
     #[test]
-    fn op_code_9x_y0_skip_if_vx_not_eq_vy_false() {
+    fn framebuffer_ascii_ibm_logo() {
         let mut chip8 = Chip8::new();
-        let vx = 0x3e;
-        let vy = 0x0a;
+        chip8
+            .load_game_from_bytes(include_bytes!("../tests/roms/ibm_logo.ch8"))
+            .unwrap();
+        chip8.run_until_idle(1000).unwrap();
+
+        let ascii = chip8.framebuffer_ascii();
+
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert_eq!(
+            lines[8].trim_end(),
+            "            ########.#########...#####.........#####".replace('.', " ")
+        );
+        assert!(lines[..8].iter().all(|line| line.trim().is_empty()));
+    }
 
-        let program_counter = 0x240;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0x93;
-        chip8.memory[program_counter + 1] = 0x60;
-        chip8.v[0x03] = vx;
-        chip8.v[0x06] = vy;
+    #[test]
+    fn op_code_dx_yn_sprite_past_end_of_memory() {
+        // 10 rows from 0xFFE, only 2 of them are in memory
+        let mut chip8 = Chip8Builder::new()
+            .i(0xffe)
+            .pc(0x200)
+            .at(0x200, [0xd0, 0x0a])
+            .at(0xffe, [0xff, 0x81])
+            .build();
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 4);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x03], vx);
-        assert_eq!(chip8.v[0x06], vy);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!(chip8.draw_stats().pixels_flipped, 8 + 2);
+        assert_eq!(chip8.gfx[..8], [1; 8]);
+        assert_eq!(chip8.gfx[64..72], [1, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(chip8.gfx[2 * 64..].iter().all(|&pixel| pixel == 0));
     }
 
     #[test]
-    fn op_code_an_nn_set_i_to_nnn() {
+    fn memory_access_near_end_of_memory() {
         let mut chip8 = Chip8::new();
 
-        let program_counter = 0x240;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0xa0;
-        chip8.memory[program_counter + 1] = 0x63;
+        let program = [
+            0xf3, 0x55, // dump V0..V3
+            0xf3, 0x65, // load V0..V3
+            0xf4, 0x33, // BCD of V4
+            0xd0, 0x0f, // draw 15 rows
+        ];
+        chip8.memory[0x200..0x208].copy_from_slice(&program);
+        chip8.v[..5].copy_from_slice(&[0x0a, 0x0b, 0x0c, 0x0d, 0xea]);
+        chip8.i = 0xffe;
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, program_counter + 2);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0x063);
-    }
+        assert_eq!(chip8.memory[0xffe..], [0x0a, 0x0b]);
+        assert_eq!(chip8.memory[..2], [0x0c, 0x0d]);
 
-    #[test]
-    fn op_code_bn_nn_goto_nnn_plus_v0() {
-        let mut chip8 = Chip8::new();
+        chip8.v[..4].fill(0);
+        chip8.execute();
 
-        let program_counter = 0x240;
-        chip8.program_counter = program_counter;
-        chip8.memory[program_counter] = 0xb2;
-        chip8.memory[program_counter + 1] = 0x63;
-        chip8.v[0x00] = 0x12;
+        assert_eq!(chip8.v[..4], [0x0a, 0x0b, 0x0c, 0x0d]);
 
         chip8.execute();
 
-        assert_eq!(chip8.state, ChipState::Run);
-        assert_eq!(chip8.program_counter, 0x12 + 0x263);
-        assert_eq!(chip8.stack_pointer, 0);
-        assert_eq!(chip8.i, 0);
-        assert_eq!(chip8.v[0x00], 0x12);
+        // 234
+        assert_eq!(chip8.memory[0xffe..], [0x02, 0x03]);
+        assert_eq!(chip8.memory[0], 0x04);
+
+        chip8.v[0x00] = 0;
+        chip8.execute();
+
+        // only the two rows left in memory are drawn
+        assert_eq!(chip8.state, ChipState::Draw);
+        assert_eq!(chip8.program_counter, 0x208);
+        let mut packed_expected = [0u8; 256];
+        packed_expected[0] = 0x02;
+        packed_expected[8] = 0x03;
+        assert_eq!(chip8.framebuffer_packed(), packed_expected);
     }
 
     // #[test]
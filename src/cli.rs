@@ -0,0 +1,331 @@
+use std::path::PathBuf;
+
+use sdl2::pixels::Color;
+
+use crate::audio::{Waveform, BEEP_FREQUENCY};
+use crate::chip8::{DrawQuirk, Profile, CYCLES_PER_FRAME, TURBO_FACTOR};
+use crate::io::{self, Theme, BACKGROUND_COLOR, DRAWING_COLOR, SCALE};
+
+pub const USAGE: &str = "\
+Usage: chip-8 [OPTIONS] <ROM>
+
+A directory instead of a ROM lists its .ch8/.c8 files, pick one with 2/8 and 5.
+
+Options:
+    --speed <N>         instructions executed per 60Hz frame (default 10)
+    --clock <HZ>        instructions executed per second, instead of --speed
+    --vip-timing        run as many instructions per frame as the COSMAC VIP
+    --turbo-factor <N>  speed multiplier while Tab is held (default 5)
+    --scale <N>         window scale factor (default 10)
+    --fg <RRGGBB>       foreground color (default FFFFFF)
+    --bg <RRGGBB>       background color (default 000000)
+    --vsync             present frames on the display refresh
+    --theme <NAME>      colors: classic, gameboy or amber, --fg/--bg after it override them
+    --terminal          run on the terminal without a window or sound
+    --beep <HZ>         beep frequency (default 440)
+    --waveform <NAME>   beep waveform: square, sine or triangle (default square)
+    --flags <FILE>      keep the SCHIP FX75/FX85 flags in FILE across runs
+    --profile <NAME>    quirks of a platform: vip, schip, xochip or modern (default modern),
+                        the quirk options below are enabled on top of it
+    --xo-chip           64K memory and the F000 NNNN long load (XO-CHIP)
+    --shift-quirk       8XY6/8XYE shift VY into VX (COSMAC VIP)
+    --wrap-x            sprites wrap around the left/right edges instead of clipping
+    --wrap-y            sprites wrap around the top/bottom edges instead of clipping
+    --display-wait      DXYN waits for the next frame (COSMAC VIP)
+    --key-snapshot      read the keypad from the keys held every frame
+    --protect-reserved  ignore writes below 0x200, where the fonts are
+    --start-paused      wait for Space before running the ROM
+    --watchdog <N>      pause after N instructions without a register change or draw
+    --disasm <FILE>     write the disassembly of the ROM to FILE and exit";
+
+// Upper bounds of the numeric options, past them the clock, the turbo clock or the window
+// size would not fit in a u32
+const MAX_SPEED: u32 = 1_000_000;
+const MAX_CLOCK_HZ: u32 = MAX_SPEED * 60;
+const MAX_TURBO_FACTOR: u32 = 64;
+const MAX_SCALE: u32 = 100;
+const MAX_BEEP_FREQUENCY: u32 = 20_000;
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub speed: u32,
+    pub clock_hz: Option<u32>,
+    pub vip_timing: bool,
+    pub turbo_factor: u32,
+    pub scale: u32,
+    pub foreground: Color,
+    pub background: Color,
+    pub vsync: bool,
+    pub terminal: bool,
+    pub beep_frequency: f32,
+    pub waveform: Waveform,
+    pub flags: Option<PathBuf>,
+    pub profile: Profile,
+    pub xo_chip: bool,
+    pub shift_quirk: bool,
+    pub draw_quirk: DrawQuirk,
+    pub display_wait_quirk: bool,
+    pub key_snapshot: bool,
+    pub protect_reserved: bool,
+    pub start_paused: bool,
+    pub watchdog: Option<u64>,
+    pub disasm: Option<PathBuf>,
+}
+
+/** Parses the command line arguments (without the program name) */
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut rom = None;
+    let mut speed = CYCLES_PER_FRAME;
+    let mut clock_hz = None;
+    let mut vip_timing = false;
+    let mut turbo_factor = TURBO_FACTOR;
+    let mut scale = SCALE;
+    let mut foreground = DRAWING_COLOR;
+    let mut background = BACKGROUND_COLOR;
+    let mut vsync = false;
+    let mut terminal = false;
+    let mut beep_frequency = BEEP_FREQUENCY;
+    let mut waveform = Waveform::Square;
+    let mut flags = None;
+    let mut profile = Profile::Modern;
+    let mut xo_chip = false;
+    let mut shift_quirk = false;
+    let mut draw_quirk = DrawQuirk::default();
+    let mut display_wait_quirk = false;
+    let mut key_snapshot = false;
+    let mut protect_reserved = false;
+    let mut start_paused = false;
+    let mut watchdog = None;
+    let mut disasm = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => speed = number(&arg, args.next(), MAX_SPEED)?,
+            "--clock" => clock_hz = Some(number(&arg, args.next(), MAX_CLOCK_HZ)?),
+            "--vip-timing" => vip_timing = true,
+            "--turbo-factor" => turbo_factor = number(&arg, args.next(), MAX_TURBO_FACTOR)?,
+            "--scale" => scale = number(&arg, args.next(), MAX_SCALE)?,
+            "--fg" => foreground = io::parse_color(&value(&arg, args.next())?)?,
+            "--bg" => background = io::parse_color(&value(&arg, args.next())?)?,
+            "--theme" => {
+                let theme: Theme = value(&arg, args.next())?.parse()?;
+                (foreground, background) = theme.colors();
+            }
+            "--vsync" => vsync = true,
+            "--terminal" => terminal = true,
+            "--beep" => beep_frequency = number(&arg, args.next(), MAX_BEEP_FREQUENCY)? as f32,
+            "--waveform" => waveform = value(&arg, args.next())?.parse()?,
+            "--flags" => flags = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--profile" => profile = value(&arg, args.next())?.parse()?,
+            "--xo-chip" => xo_chip = true,
+            "--shift-quirk" => shift_quirk = true,
+            "--wrap-x" => draw_quirk.wrap_horizontal = true,
+            "--wrap-y" => draw_quirk.wrap_vertical = true,
+            "--display-wait" => display_wait_quirk = true,
+            "--key-snapshot" => key_snapshot = true,
+            "--protect-reserved" => protect_reserved = true,
+            "--start-paused" => start_paused = true,
+            "--watchdog" => watchdog = Some(u64::from(number(&arg, args.next(), u32::MAX)?)),
+            "--disasm" => disasm = Some(PathBuf::from(value(&arg, args.next())?)),
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(Options {
+        rom: rom.ok_or("no ROM path given")?,
+        speed,
+        clock_hz,
+        vip_timing,
+        turbo_factor,
+        scale,
+        foreground,
+        background,
+        vsync,
+        terminal,
+        beep_frequency,
+        waveform,
+        flags,
+        profile,
+        xo_chip,
+        shift_quirk,
+        draw_quirk,
+        display_wait_quirk,
+        key_snapshot,
+        protect_reserved,
+        start_paused,
+        watchdog,
+        disasm,
+    })
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or(format!("{} needs a value", option))
+}
+
+/** Parses a number from 1 to `max` */
+fn number(option: &str, value: Option<String>, max: u32) -> Result<u32, String> {
+    let value = self::value(option, value)?;
+    match value.parse() {
+        Ok(number) if number > 0 && number <= max => Ok(number),
+        _ => Err(format!("invalid value for {}: {}", option, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let options = parse(args(&["games/pong2.c8"])).unwrap();
+
+        assert_eq!(
+            options,
+            Options {
+                rom: PathBuf::from("games/pong2.c8"),
+                speed: CYCLES_PER_FRAME,
+                clock_hz: None,
+                vip_timing: false,
+                turbo_factor: TURBO_FACTOR,
+                scale: SCALE,
+                foreground: DRAWING_COLOR,
+                background: BACKGROUND_COLOR,
+                vsync: false,
+                terminal: false,
+                beep_frequency: BEEP_FREQUENCY,
+                waveform: Waveform::Square,
+                flags: None,
+                profile: Profile::Modern,
+                xo_chip: false,
+                shift_quirk: false,
+                draw_quirk: DrawQuirk::default(),
+                display_wait_quirk: false,
+                key_snapshot: false,
+                protect_reserved: false,
+                start_paused: false,
+                watchdog: None,
+                disasm: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = parse(args(&[
+            "--speed",
+            "20",
+            "--clock",
+            "500",
+            "--vip-timing",
+            "--turbo-factor",
+            "3",
+            "--scale",
+            "5",
+            "--fg",
+            "#ffb000",
+            "--bg",
+            "101010",
+            "--vsync",
+            "--terminal",
+            "--beep",
+            "880",
+            "--waveform",
+            "triangle",
+            "--flags",
+            "tetris.flags",
+            "--profile",
+            "vip",
+            "--xo-chip",
+            "--shift-quirk",
+            "--wrap-x",
+            "--display-wait",
+            "--key-snapshot",
+            "--protect-reserved",
+            "--start-paused",
+            "--watchdog",
+            "100000",
+            "--disasm",
+            "tetris.txt",
+            "games/tetris.c8",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            options,
+            Options {
+                rom: PathBuf::from("games/tetris.c8"),
+                speed: 20,
+                clock_hz: Some(500),
+                vip_timing: true,
+                turbo_factor: 3,
+                scale: 5,
+                foreground: Color::RGB(0xff, 0xb0, 0x00),
+                background: Color::RGB(0x10, 0x10, 0x10),
+                vsync: true,
+                terminal: true,
+                beep_frequency: 880.0,
+                waveform: Waveform::Triangle,
+                flags: Some(PathBuf::from("tetris.flags")),
+                profile: Profile::CosmacVip,
+                xo_chip: true,
+                shift_quirk: true,
+                draw_quirk: DrawQuirk {
+                    wrap_horizontal: true,
+                    wrap_vertical: false,
+                },
+                display_wait_quirk: true,
+                key_snapshot: true,
+                protect_reserved: true,
+                start_paused: true,
+                watchdog: Some(100_000),
+                disasm: Some(PathBuf::from("tetris.txt")),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_theme() {
+        let options = parse(args(&["--theme", "amber", "--bg", "000000", "rom.c8"])).unwrap();
+
+        assert_eq!(options.foreground, Theme::Amber.colors().0);
+        assert_eq!(options.background, Color::BLACK);
+        assert_eq!(
+            parse(args(&["--theme", "neon", "rom.c8"])),
+            Err("invalid theme: neon".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse(args(&[])), Err("no ROM path given".to_string()));
+        assert_eq!(
+            parse(args(&["rom.c8", "--speed"])),
+            Err("--speed needs a value".to_string())
+        );
+        assert_eq!(
+            parse(args(&["--scale", "0", "rom.c8"])),
+            Err("invalid value for --scale: 0".to_string())
+        );
+        assert_eq!(
+            parse(args(&["--speed", "100000000", "rom.c8"])),
+            Err("invalid value for --speed: 100000000".to_string())
+        );
+        assert_eq!(
+            parse(args(&["--turbo", "rom.c8"])),
+            Err("unknown option: --turbo".to_string())
+        );
+        assert_eq!(
+            parse(args(&["a.c8", "b.c8"])),
+            Err("unexpected argument: b.c8".to_string())
+        );
+    }
+}
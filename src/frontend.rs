@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use crate::chip8::ChipState;
+use crate::keypad::Keypad;
+
+/** The file name of a ROM for display, non UTF-8 characters being replaced */
+pub fn rom_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/** Where the display is shown (SDL window, terminal, or a test double) */
+pub trait Display {
+    /** Shows the framebuffer, `width` x `height` pixels */
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize) -> Result<(), String>;
+
+    fn clear(&mut self) -> Result<(), String>;
+
+    /** Shows or hides the instructions/s and frames/s overlay */
+    fn toggle_stats(&mut self) {}
+
+    /** Pixels (gfx indices) to highlight in the next draws, for the collision debug mode */
+    fn set_highlight(&mut self, _pixels: &[usize]) {}
+
+    /** Shows the name of the ROM being played, or selected in the menu */
+    fn set_rom_name(&mut self, _rom_name: &str) {}
+
+    /** Shows whether the emulation is paused */
+    fn set_paused(&mut self, _paused: bool) {}
+
+    /** Counts an emulated frame, `cycles` being the number of instructions executed so far */
+    fn frame_done(&mut self, _cycles: u64) {}
+
+    fn save_screenshot(
+        &self,
+        _gfx: &[u8],
+        _width: usize,
+        _height: usize,
+        _path: &Path,
+    ) -> Result<(), String> {
+        Err("screenshots are not supported by this display".to_string())
+    }
+}
+
+/** Where the keypad and the emulator controls come from */
+pub trait Input {
+    /** Updates the keypad, and the machine state for quit/pause requests. Called once per frame */
+    fn poll(&mut self, keys: &mut Keypad, state: &mut ChipState);
+
+    /** Returns whether a screenshot was requested, and resets the request */
+    fn screenshot_requested(&mut self) -> bool {
+        false
+    }
+
+    /** Returns whether the stats overlay was toggled, and resets the request */
+    fn stats_toggle_requested(&mut self) -> bool {
+        false
+    }
+
+    /** Returns the reset requested since the last check, Some(true) for a cold reset (see
+     * `Chip8::reset`), and resets the request */
+    fn reset_requested(&mut self) -> Option<bool> {
+        None
+    }
+
+    /** Returns whether the collision debug mode was toggled, and resets the request */
+    fn collision_debug_toggle_requested(&mut self) -> bool {
+        false
+    }
+
+    fn turbo_held(&self) -> bool {
+        false
+    }
+
+    fn rewind_held(&self) -> bool {
+        false
+    }
+}
+
+/** Plays the beep while the sound timer is active */
+pub trait Sound {
+    fn play(&mut self, on: bool);
+
+    /** Plays the 128 bit XO-CHIP pattern, one bit per sample at `rate` Hz, instead of the
+     * beep. None goes back to the beep */
+    fn set_pattern(&mut self, _pattern: Option<[u8; 16]>, _rate: f32) {}
+}
+
+/** No sound, for headless runs */
+impl Sound for () {
+    fn play(&mut self, _on: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn rom_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(rom_name(Path::new("games/tetris.c8")), "tetris.c8");
+        assert_eq!(
+            rom_name(Path::new(OsStr::from_bytes(b"games/t\xffris.c8"))),
+            "t\u{fffd}ris.c8"
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use std::fmt;
+
+/** A decoded CHIP-8 / SCHIP instruction.
+ * X and Y are register indexes, NN a byte and NNN a 12 bit address */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // 0NNN, a machine code routine of the original interpreters
+    Sys(u16),
+    // 00CN
+    ScrollDown(u8),
+    // 00E0
+    ClearScreen,
+    // 00EE
+    Return,
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD
+    Exit,
+    // 00FE
+    Lores,
+    // 00FF
+    Hires,
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipIfEqNn { x: usize, nn: u8 },
+    // 4XNN
+    SkipIfNotEqNn { x: usize, nn: u8 },
+    // 5XY0
+    SkipIfEqVy { x: usize, y: usize },
+    // 6XNN
+    SetVxNn { x: usize, nn: u8 },
+    // 7XNN
+    AddVxNn { x: usize, nn: u8 },
+    // 8XY0
+    SetVxVy { x: usize, y: usize },
+    // 8XY1
+    Or { x: usize, y: usize },
+    // 8XY2
+    And { x: usize, y: usize },
+    // 8XY3
+    Xor { x: usize, y: usize },
+    // 8XY4
+    AddVxVy { x: usize, y: usize },
+    // 8XY5
+    SubVxVy { x: usize, y: usize },
+    // 8XY6
+    ShiftRight { x: usize, y: usize },
+    // 8XY7
+    SubVyVx { x: usize, y: usize },
+    // 8XYE
+    ShiftLeft { x: usize, y: usize },
+    // 9XY0
+    SkipIfNotEqVy { x: usize, y: usize },
+    // ANNN
+    SetI(u16),
+    // BNNN
+    JumpV0(u16),
+    // CXNN
+    Rand { x: usize, nn: u8 },
+    // DXYN
+    Draw { x: usize, y: usize, n: u8 },
+    // EX9E
+    SkipIfKey { x: usize },
+    // EXA1
+    SkipIfNotKey { x: usize },
+    // FX07
+    GetDelayTimer { x: usize },
+    // FX0A
+    WaitKey { x: usize },
+    // FX15
+    SetDelayTimer { x: usize },
+    // FX18
+    SetSoundTimer { x: usize },
+    // FX1E
+    AddVxToI { x: usize },
+    // FX29
+    SetIToSprite { x: usize },
+    // FX30
+    SetIToBigSprite { x: usize },
+    // FN01 (XO-CHIP)
+    SelectPlane { n: u8 },
+    // F000 NNNN, NNNN is read from the next 2 bytes (XO-CHIP)
+    LongLoadI,
+    // F002 (XO-CHIP)
+    LoadAudioPattern,
+    // FX3A (XO-CHIP)
+    SetPitch { x: usize },
+    // FX33
+    Bcd { x: usize },
+    // FX55
+    RegDump { x: usize },
+    // FX65
+    RegLoad { x: usize },
+    // FX75 (SCHIP)
+    SaveFlags { x: usize },
+    // FX85 (SCHIP)
+    LoadFlags { x: usize },
+    // Unknown opcode
+    Invalid(u16),
+}
+
+impl Instruction {
+    /** The opcode class, e.g. "8XY4", or None for an unknown opcode */
+    pub fn pattern(&self) -> Option<&'static str> {
+        let pattern = match self {
+            Instruction::Sys(_) => "0NNN",
+            Instruction::ScrollDown(_) => "00CN",
+            Instruction::ClearScreen => "00E0",
+            Instruction::Return => "00EE",
+            Instruction::ScrollRight => "00FB",
+            Instruction::ScrollLeft => "00FC",
+            Instruction::Exit => "00FD",
+            Instruction::Lores => "00FE",
+            Instruction::Hires => "00FF",
+            Instruction::Jump(_) => "1NNN",
+            Instruction::Call(_) => "2NNN",
+            Instruction::SkipIfEqNn { .. } => "3XNN",
+            Instruction::SkipIfNotEqNn { .. } => "4XNN",
+            Instruction::SkipIfEqVy { .. } => "5XY0",
+            Instruction::SetVxNn { .. } => "6XNN",
+            Instruction::AddVxNn { .. } => "7XNN",
+            Instruction::SetVxVy { .. } => "8XY0",
+            Instruction::Or { .. } => "8XY1",
+            Instruction::And { .. } => "8XY2",
+            Instruction::Xor { .. } => "8XY3",
+            Instruction::AddVxVy { .. } => "8XY4",
+            Instruction::SubVxVy { .. } => "8XY5",
+            Instruction::ShiftRight { .. } => "8XY6",
+            Instruction::SubVyVx { .. } => "8XY7",
+            Instruction::ShiftLeft { .. } => "8XYE",
+            Instruction::SkipIfNotEqVy { .. } => "9XY0",
+            Instruction::SetI(_) => "ANNN",
+            Instruction::JumpV0(_) => "BNNN",
+            Instruction::Rand { .. } => "CXNN",
+            Instruction::Draw { .. } => "DXYN",
+            Instruction::SkipIfKey { .. } => "EX9E",
+            Instruction::SkipIfNotKey { .. } => "EXA1",
+            Instruction::GetDelayTimer { .. } => "FX07",
+            Instruction::WaitKey { .. } => "FX0A",
+            Instruction::SetDelayTimer { .. } => "FX15",
+            Instruction::SetSoundTimer { .. } => "FX18",
+            Instruction::AddVxToI { .. } => "FX1E",
+            Instruction::SetIToSprite { .. } => "FX29",
+            Instruction::SetIToBigSprite { .. } => "FX30",
+            Instruction::SelectPlane { .. } => "FN01",
+            Instruction::LongLoadI => "F000",
+            Instruction::LoadAudioPattern => "F002",
+            Instruction::SetPitch { .. } => "FX3A",
+            Instruction::Bcd { .. } => "FX33",
+            Instruction::RegDump { .. } => "FX55",
+            Instruction::RegLoad { .. } => "FX65",
+            Instruction::SaveFlags { .. } => "FX75",
+            Instruction::LoadFlags { .. } => "FX85",
+            Instruction::Invalid(_) => return None,
+        };
+        Some(pattern)
+    }
+
+    /** Approximate number of COSMAC VIP machine cycles (8 clock periods of the 1.76 MHz
+     * CDP1802) the original interpreter spends on the instruction, averaged where it depends
+     * on the data. Instructions the VIP does not know cost as much as a simple ALU instruction */
+    pub fn cycle_cost(&self) -> u32 {
+        match *self {
+            Instruction::ClearScreen => 3078,
+            Instruction::Return => 10,
+            Instruction::Jump(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::SkipIfEqNn { .. } | Instruction::SkipIfNotEqNn { .. } => 10,
+            Instruction::SkipIfEqVy { .. } | Instruction::SkipIfNotEqVy { .. } => 14,
+            Instruction::SetVxNn { .. } => 6,
+            Instruction::AddVxNn { .. } => 10,
+            Instruction::SetVxVy { .. } => 12,
+            Instruction::Or { .. }
+            | Instruction::And { .. }
+            | Instruction::Xor { .. }
+            | Instruction::AddVxVy { .. }
+            | Instruction::SubVxVy { .. }
+            | Instruction::ShiftRight { .. }
+            | Instruction::SubVyVx { .. }
+            | Instruction::ShiftLeft { .. } => 44,
+            Instruction::SetI(_) => 12,
+            Instruction::JumpV0(_) => 22,
+            Instruction::Rand { .. } => 36,
+            // 16 rows for DXY0
+            Instruction::Draw { n, .. } => 68 + 40 * u32::from(if n == 0 { 16 } else { n }),
+            Instruction::SkipIfKey { .. } | Instruction::SkipIfNotKey { .. } => 14,
+            Instruction::GetDelayTimer { .. }
+            | Instruction::SetDelayTimer { .. }
+            | Instruction::SetSoundTimer { .. } => 10,
+            Instruction::WaitKey { .. } => 19,
+            Instruction::AddVxToI { .. } | Instruction::SetIToSprite { .. } => 16,
+            Instruction::Bcd { .. } => 80,
+            Instruction::RegDump { x } | Instruction::RegLoad { x } => 14 + 14 * (x as u32 + 1),
+            _ => 44,
+        }
+    }
+}
+
+/** Decodes a raw opcode without executing it */
+pub fn decode(op_code: u16) -> Instruction {
+    let x = usize::from((op_code & 0x0F00) >> 8);
+    let y = usize::from((op_code & 0x00F0) >> 4);
+    let [_, nn] = op_code.to_be_bytes();
+    let n = nn & 0x0F;
+    let nnn = op_code & 0x0FFF;
+
+    match op_code & 0xF000 {
+        0x0000 => match op_code {
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x00C0..=0x00CF => Instruction::ScrollDown(n),
+            0x00F0..=0x00FF => match n {
+                0xB => Instruction::ScrollRight,
+                0xC => Instruction::ScrollLeft,
+                0xD => Instruction::Exit,
+                0xE => Instruction::Lores,
+                0xF => Instruction::Hires,
+                _ => Instruction::Invalid(op_code),
+            },
+            _ => Instruction::Sys(nnn),
+        },
+        0x1000 => Instruction::Jump(nnn),
+        0x2000 => Instruction::Call(nnn),
+        0x3000 => Instruction::SkipIfEqNn { x, nn },
+        0x4000 => Instruction::SkipIfNotEqNn { x, nn },
+        0x5000 => match n {
+            0x0 => Instruction::SkipIfEqVy { x, y },
+            _ => Instruction::Invalid(op_code),
+        },
+        0x6000 => Instruction::SetVxNn { x, nn },
+        0x7000 => Instruction::AddVxNn { x, nn },
+        0x8000 => match n {
+            0x0 => Instruction::SetVxVy { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddVxVy { x, y },
+            0x5 => Instruction::SubVxVy { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubVyVx { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => Instruction::Invalid(op_code),
+        },
+        0x9000 => Instruction::SkipIfNotEqVy { x, y },
+        0xA000 => Instruction::SetI(nnn),
+        0xB000 => Instruction::JumpV0(nnn),
+        0xC000 => Instruction::Rand { x, nn },
+        0xD000 => Instruction::Draw { x, y, n },
+        0xE000 => match n {
+            0xE => Instruction::SkipIfKey { x },
+            0x1 => Instruction::SkipIfNotKey { x },
+            _ => Instruction::Invalid(op_code),
+        },
+        _ if op_code == 0xF000 => Instruction::LongLoadI,
+        _ if op_code == 0xF002 => Instruction::LoadAudioPattern,
+        _ => match nn {
+            0x01 => Instruction::SelectPlane { n: x as u8 },
+            0x07 => Instruction::GetDelayTimer { x },
+            0x0A => Instruction::WaitKey { x },
+            0x15 => Instruction::SetDelayTimer { x },
+            0x18 => Instruction::SetSoundTimer { x },
+            0x1E => Instruction::AddVxToI { x },
+            0x29 => Instruction::SetIToSprite { x },
+            0x30 => Instruction::SetIToBigSprite { x },
+            0x33 => Instruction::Bcd { x },
+            0x3A => Instruction::SetPitch { x },
+            0x55 => Instruction::RegDump { x },
+            0x65 => Instruction::RegLoad { x },
+            0x75 => Instruction::SaveFlags { x },
+            0x85 => Instruction::LoadFlags { x },
+            _ => Instruction::Invalid(op_code),
+        },
+    }
+}
+
+/** Approximate number of COSMAC VIP machine cycles of an opcode, see `Instruction::cycle_cost` */
+pub fn cycle_cost(op_code: u16) -> u32 {
+    decode(op_code).cycle_cost()
+}
+
+/** Formats the instruction in the mnemonic syntax read by `asm::assemble` */
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS {:#05x}", nnn),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::Jump(nnn) => write!(f, "JP {:#05x}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL {:#05x}", nnn),
+            Instruction::SkipIfEqNn { x, nn } => write!(f, "SE V{:X}, {:#04x}", x, nn),
+            Instruction::SkipIfNotEqNn { x, nn } => write!(f, "SNE V{:X}, {:#04x}", x, nn),
+            Instruction::SkipIfEqVy { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SetVxNn { x, nn } => write!(f, "LD V{:X}, {:#04x}", x, nn),
+            Instruction::AddVxNn { x, nn } => write!(f, "ADD V{:X}, {:#04x}", x, nn),
+            Instruction::SetVxVy { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddVxVy { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SubVxVy { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubVyVx { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfNotEqVy { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetI(nnn) => write!(f, "LD I, {:#05x}", nnn),
+            Instruction::JumpV0(nnn) => write!(f, "JP V0, {:#05x}", nnn),
+            Instruction::Rand { x, nn } => write!(f, "RND V{:X}, {:#04x}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfKey { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfNotKey { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::GetDelayTimer { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddVxToI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::SetIToSprite { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::SetIToBigSprite { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::SelectPlane { n } => write!(f, "PLANE {}", n),
+            Instruction::LongLoadI => write!(f, "LD I, LONG"),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::SetPitch { x } => write!(f, "LD PITCH, V{:X}", x),
+            Instruction::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::RegDump { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::RegLoad { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            // Data, or an opcode this interpreter does not know
+            Instruction::Invalid(op_code) => write!(f, "DW {:#06x}", op_code),
+        }
+    }
+}
+
+/** Disassembles a ROM one 2 byte word at a time (a linear sweep, data is decoded as code).
+ * A trailing odd byte is listed as DB */
+pub fn disassemble(rom: &[u8]) -> Vec<String> {
+    rom.chunks(2)
+        .map(|word| match *word {
+            [high, low] => decode(u16::from_be_bytes([high, low])).to_string(),
+            _ => format!("DB {:#04x}", word[0]),
+        })
+        .collect()
+}
+
+/** The disassembly of a ROM loaded at `address`, one "address: mnemonic" line per word */
+pub fn listing(rom: &[u8], address: usize) -> String {
+    disassemble(rom)
+        .iter()
+        .enumerate()
+        .map(|(index, line)| format!("{:#05x}: {}\n", address + index * 2, line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn decode_system_instructions() {
+        assert_eq!(decode(0x00E0), Instruction::ClearScreen);
+        assert_eq!(decode(0x00EE), Instruction::Return);
+        assert_eq!(decode(0x00C5), Instruction::ScrollDown(5));
+        assert_eq!(decode(0x00FB), Instruction::ScrollRight);
+        assert_eq!(decode(0x00FF), Instruction::Hires);
+        assert_eq!(decode(0x0123), Instruction::Sys(0x123));
+        assert_eq!(decode(0x0000), Instruction::Sys(0x000));
+    }
+
+    #[test]
+    fn decode_operands() {
+        assert_eq!(decode(0x1234), Instruction::Jump(0x234));
+        assert_eq!(decode(0x2ABC), Instruction::Call(0xABC));
+        assert_eq!(decode(0x6A42), Instruction::SetVxNn { x: 0xA, nn: 0x42 });
+        assert_eq!(decode(0x8CD4), Instruction::AddVxVy { x: 0xC, y: 0xD });
+        assert_eq!(decode(0x812E), Instruction::ShiftLeft { x: 1, y: 2 });
+        assert_eq!(decode(0xD125), Instruction::Draw { x: 1, y: 2, n: 5 });
+        assert_eq!(decode(0xE39E), Instruction::SkipIfKey { x: 3 });
+        assert_eq!(decode(0xF70A), Instruction::WaitKey { x: 7 });
+        assert_eq!(decode(0xF430), Instruction::SetIToBigSprite { x: 4 });
+        assert_eq!(decode(0xF000), Instruction::LongLoadI);
+        assert_eq!(decode(0xF201), Instruction::SelectPlane { n: 2 });
+        assert_eq!(decode(0xF002), Instruction::LoadAudioPattern);
+        assert_eq!(decode(0xF53A), Instruction::SetPitch { x: 5 });
+        assert_eq!(decode(0xF375), Instruction::SaveFlags { x: 3 });
+        assert_eq!(decode(0xF385), Instruction::LoadFlags { x: 3 });
+        assert_eq!(decode(0xF265), Instruction::RegLoad { x: 2 });
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(decode(0x00F0), Instruction::Invalid(0x00F0));
+        assert_eq!(decode(0x5121), Instruction::Invalid(0x5121));
+        assert_eq!(decode(0x812F), Instruction::Invalid(0x812F));
+        assert_eq!(decode(0xE1FF), Instruction::Invalid(0xE1FF));
+        assert_eq!(decode(0xF0FF), Instruction::Invalid(0xF0FF));
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(decode(0x00C3).pattern(), Some("00CN"));
+        assert_eq!(decode(0x8CD4).pattern(), Some("8XY4"));
+        assert_eq!(decode(0xF000).pattern(), Some("F000"));
+        assert_eq!(decode(0xF0FF).pattern(), None);
+    }
+
+    #[test]
+    fn display_mnemonics() {
+        assert_eq!(decode(0x00E0).to_string(), "CLS");
+        assert_eq!(decode(0x1234).to_string(), "JP 0x234");
+        assert_eq!(decode(0x6A0F).to_string(), "LD VA, 0x0f");
+        assert_eq!(decode(0xD125).to_string(), "DRW V1, V2, 5");
+        assert_eq!(decode(0xF355).to_string(), "LD [I], V3");
+        assert_eq!(decode(0x5121).to_string(), "DW 0x5121");
+    }
+
+    #[test]
+    fn cycle_costs() {
+        assert_eq!(cycle_cost(0x6012), 6);
+        assert_eq!(cycle_cost(0x8124), 44);
+        assert_eq!(cycle_cost(0x00E0), 3078);
+        assert_eq!(cycle_cost(0xD015), 268);
+        assert_eq!(cycle_cost(0xF255), 56);
+        assert!(cycle_cost(0xD011) > cycle_cost(0x7001));
+    }
+
+    #[test]
+    fn disassemble_rom() {
+        assert_eq!(
+            disassemble(&[0x00, 0xe0, 0xa2, 0x0a, 0xff]),
+            vec!["CLS", "LD I, 0x20a", "DB 0xff"]
+        );
+    }
+
+    #[test]
+    fn listing_rom() {
+        assert_eq!(
+            listing(&[0x00, 0xe0, 0xa2, 0x0a, 0x12, 0x00, 0xff], 0x200),
+            "0x200: CLS\n0x202: LD I, 0x20a\n0x204: JP 0x200\n0x206: DB 0xff\n"
+        );
+    }
+}
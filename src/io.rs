@@ -1,180 +1,742 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use image::{ImageFormat, Rgb, RgbImage};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod, Scancode};
 use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
-use sdl2::video::Window;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use sdl2::video::{Window, WindowContext};
 use sdl2::{EventPump, Sdl};
 
-use crate::chip8::ChipState;
+use crate::audio::Audio;
+use crate::chip8::{ChipState, CHIP8_FONT_SET};
+use crate::frontend::{Display, Input};
+use crate::keypad::Keypad;
 
-const DISPLAY_WIDTH: u16 = 64;
-const DISPLAY_HEIGHT: u16 = 32;
-const SCALE: u16 = 10;
-const BACKGROUND_COLOR: Color = Color::BLACK;
-const DRAWING_COLOR: Color = Color::WHITE;
+const DISPLAY_WIDTH: u32 = 64;
+const DISPLAY_HEIGHT: u32 = 32;
+pub const SCALE: u32 = 10;
+pub const BACKGROUND_COLOR: Color = Color::BLACK;
+pub const DRAWING_COLOR: Color = Color::WHITE;
+// XO-CHIP pixels lit in plane 2 only, and in both planes
+const PLANE_2_COLOR: Color = Color::RGB(0x55, 0x55, 0x55);
+const BOTH_PLANES_COLOR: Color = Color::RGB(0xaa, 0xaa, 0xaa);
+// Pixels turned off by a sprite collision, in collision debug mode
+const COLLISION_COLOR: Color = Color::RGB(0xff, 0x30, 0x30);
+// Window pixels per font pixel in the stats overlay
+const STATS_PIXEL_SIZE: i32 = 2;
 
 pub struct IOContext {
     pub renderer: Renderer,
     pub keyboard: Keyboard,
+    pub audio: Audio,
 }
 
 impl IOContext {
-    pub fn new() -> Result<IOContext, String> {
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
+    /** Opens the window titled with the ROM name (see `frontend::rom_name`), the audio and
+     * the keyboard. With `vsync` the frames are presented on the display refresh */
+    pub fn new(
+        scale: u32,
+        foreground: Color,
+        background: Color,
+        rom_name: &str,
+        vsync: bool,
+    ) -> Result<IOContext, String> {
+        let sdl_context = sdl2::init().map_err(|e| format!("could not initialize SDL: {}", e))?;
+        let video_subsystem = sdl_context
+            .video()
+            .map_err(|e| format!("could not initialize video subsystem: {}", e))?;
 
         let window = video_subsystem
             .window(
-                "Chip-8",
-                (DISPLAY_WIDTH * SCALE).try_into().unwrap(),
-                (DISPLAY_HEIGHT * SCALE).try_into().unwrap(),
+                &window_title(rom_name, false),
+                DISPLAY_WIDTH * scale,
+                DISPLAY_HEIGHT * scale,
             )
             .position_centered()
             .opengl()
             .build()
-            .unwrap();
+            .map_err(|e| format!("could not create window: {}", e))?;
+
+        let mut renderer = Renderer::new(window, scale, vsync)?;
+        renderer.rom_name = rom_name.to_string();
+        renderer.set_palette(foreground, background);
+
+        let audio_subsystem = sdl_context
+            .audio()
+            .map_err(|e| format!("could not initialize audio subsystem: {}", e))?;
+        let audio = Audio::new(&audio_subsystem)
+            .map_err(|e| format!("could not open audio device: {}", e))?;
 
-        let renderer = Renderer::new(window)?;
+        let keyboard = Keyboard::new(sdl_context)?;
 
-        let keyboard = Keyboard::new(sdl_context);
+        Ok(IOContext {
+            renderer,
+            keyboard,
+            audio,
+        })
+    }
+}
 
-        Ok(IOContext { renderer, keyboard })
+/** "Chip-8 — <rom>", followed by " [Paused]" while paused */
+fn window_title(rom_name: &str, paused: bool) -> String {
+    let mut title = "Chip-8".to_string();
+    if !rom_name.is_empty() {
+        title += " — ";
+        title += rom_name;
     }
+    if paused {
+        title += " [Paused]";
+    }
+    title
+}
+
+/** Named foreground/background color pairs */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Theme {
+    // white on black
+    Classic,
+    // dark green on light green, #0F380F on #9BBC0F
+    GameBoy,
+    // amber on a dark brown, #FFB000 on #1A1000
+    Amber,
+}
+
+impl Theme {
+    /** The (foreground, background) colors */
+    pub fn colors(&self) -> (Color, Color) {
+        match self {
+            Theme::Classic => (DRAWING_COLOR, BACKGROUND_COLOR),
+            Theme::GameBoy => (Color::RGB(0x0f, 0x38, 0x0f), Color::RGB(0x9b, 0xbc, 0x0f)),
+            Theme::Amber => (Color::RGB(0xff, 0xb0, 0x00), Color::RGB(0x1a, 0x10, 0x00)),
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Theme, String> {
+        match name {
+            "classic" => Ok(Theme::Classic),
+            "gameboy" => Ok(Theme::GameBoy),
+            "amber" => Ok(Theme::Amber),
+            _ => Err(format!("invalid theme: {}", name)),
+        }
+    }
+}
+
+/** Parses a hex color such as "#FFB000" or "ffb000" */
+pub fn parse_color(hex: &str) -> Result<Color, String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    // from_str_radix would accept a sign
+    if digits.len() != 6 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("invalid color: {}", hex));
+    }
+    let rgb = u32::from_str_radix(digits, 16).map_err(|_| format!("invalid color: {}", hex))?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Ok(Color::RGB(r, g, b))
+}
+
+/** Writes the framebuffer into a locked RGB24 texture buffer. The palette is indexed by the
+ * pixel planes: background, plane 1, plane 2 and both planes */
+fn fill_texture(buffer: &mut [u8], pitch: usize, gfx: &[u8], width: usize, palette: &[Color; 4]) {
+    for (i, pix) in gfx.iter().enumerate() {
+        let color = palette[usize::from(*pix & 0x3)];
+        let offset = (i / width) * pitch + (i % width) * 3;
+        buffer[offset..offset + 3].copy_from_slice(&[color.r, color.g, color.b]);
+    }
+}
+
+/** Paints pixels (framebuffer indices) of a locked RGB24 texture buffer */
+fn tint_pixels(buffer: &mut [u8], pitch: usize, pixels: &[usize], width: usize, color: Color) {
+    for i in pixels {
+        let offset = (i / width) * pitch + (i % width) * 3;
+        buffer[offset..offset + 3].copy_from_slice(&[color.r, color.g, color.b]);
+    }
+}
+
+/** Renders the framebuffer at the window size, the way it is shown on screen */
+fn screenshot_image(
+    gfx: &[u8],
+    width: usize,
+    height: usize,
+    scale: u32,
+    palette: &[Color; 4],
+) -> RgbImage {
+    let image_width = DISPLAY_WIDTH * scale;
+    let image_height = DISPLAY_HEIGHT * scale;
+    RgbImage::from_fn(image_width, image_height, |x, y| {
+        let x = usize::try_from(x).unwrap() * width / usize::try_from(image_width).unwrap();
+        let y = usize::try_from(y).unwrap() * height / usize::try_from(image_height).unwrap();
+        let color = palette[usize::from(gfx[y * width + x] & 0x3)];
+        Rgb([color.r, color.g, color.b])
+    })
+}
+
+/** Lit pixels of a decimal number drawn with the 4x5 font, relative to its top left corner */
+fn number_pixels(number: u64) -> Vec<(i32, i32)> {
+    let mut pixels = Vec::new();
+    for (position, digit) in number.to_string().bytes().enumerate() {
+        let glyph = usize::from(digit - b'0') * 5;
+        for (y, row) in CHIP8_FONT_SET[glyph..glyph + 5].iter().enumerate() {
+            for x in 0..4 {
+                if row & (0x80 >> x) != 0 {
+                    // 4 pixels wide glyphs with a 1 pixel gap
+                    let x = i32::try_from(position * 5 + x).unwrap();
+                    pixels.push((x, i32::try_from(y).unwrap()));
+                }
+            }
+        }
+    }
+    pixels
+}
+
+fn write_png(image: &RgbImage, path: &Path) -> Result<(), String> {
+    image
+        .save_with_format(path, ImageFormat::Png)
+        .map_err(|e| format!("could not save screenshot {}: {}", path.display(), e))
+}
+
+/** Instructions and frames per second, measured over one second */
+struct Stats {
+    start: Instant,
+    start_cycles: u64,
+    frames: u32,
+    ips: u64,
+    fps: u32,
 }
 
 pub struct Renderer {
     canvas: WindowCanvas,
+    scale: u32,
+    texture_creator: TextureCreator<WindowContext>,
+    // native resolution frame, stretched over the window by a single copy
+    texture: Option<Texture>,
+    // background, plane 1, plane 2 and both planes colors
+    palette: [Color; 4],
+    // instructions/s and frames/s in the top left corner, toggled with F3
+    stats_visible: bool,
+    stats: Stats,
+    // pixels painted with COLLISION_COLOR, toggled with F4
+    highlight: Vec<usize>,
+    // shown in the window title
+    rom_name: String,
+    paused: bool,
+    // present waits for the display refresh
+    vsync: bool,
 }
 
 impl Renderer {
-    pub fn new(window: Window) -> Result<Renderer, String> {
-        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    /** Creates the renderer of the window. With `vsync` presenting a frame blocks until the
+     * display refresh, smoothing the animation, without it frames are presented immediately
+     * (for benchmarks) */
+    pub fn new(window: Window, scale: u32, vsync: bool) -> Result<Renderer, String> {
+        let mut builder = window.into_canvas();
+        if vsync {
+            builder = builder.present_vsync();
+        }
+        let canvas = builder
+            .build()
+            .map_err(|e| format!("could not create renderer: {}", e))?;
+        let texture_creator = canvas.texture_creator();
+
+        Ok(Renderer {
+            canvas,
+            scale,
+            texture_creator,
+            texture: None,
+            palette: [
+                BACKGROUND_COLOR,
+                DRAWING_COLOR,
+                PLANE_2_COLOR,
+                BOTH_PLANES_COLOR,
+            ],
+            stats_visible: false,
+            stats: Stats {
+                start: Instant::now(),
+                start_cycles: 0,
+                frames: 0,
+                ips: 0,
+                fps: 0,
+            },
+            highlight: Vec::new(),
+            rom_name: String::new(),
+            paused: false,
+            vsync,
+        })
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    /** Switches vsync on the live canvas. The sdl2 crate only sets it when the canvas is built,
+     * SDL_RenderSetVSync (SDL 2.0.18) changes it without recreating the renderer */
+    pub fn set_vsync(&mut self, vsync: bool) -> Result<(), String> {
+        // The raw renderer lives as long as the canvas
+        let result = unsafe { sdl2::sys::SDL_RenderSetVSync(self.canvas.raw(), i32::from(vsync)) };
+        if result != 0 {
+            return Err(format!("could not set vsync: {}", sdl2::get_error()));
+        }
+        self.vsync = vsync;
+        Ok(())
+    }
+
+    fn update_title(&mut self) {
+        let title = window_title(&self.rom_name, self.paused);
+        // File names cannot contain the NUL byte SDL rejects
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
 
-        Ok(Renderer { canvas })
+    pub fn set_palette(&mut self, fg: Color, bg: Color) {
+        self.palette[0] = bg;
+        self.palette[1] = fg;
     }
 
-    pub fn draw(&mut self, gfx: [u8; 64 * 32]) -> Result<(), String> {
-        self.clear();
-        for (i, pix) in gfx.iter().enumerate() {
-            if *pix == 1 {
-                let x = i % 64;
-                let y = i / 64;
-                self.draw_dot(x, y)?;
+    /** Draws the instructions/s and frames/s on two lines. The overlay is drawn over the
+     * game frame, so it does not show up in the framebuffer or in screenshots */
+    fn draw_stats(&mut self) -> Result<(), String> {
+        let lines = [self.stats.ips, u64::from(self.stats.fps)];
+        let rects: Vec<Rect> = lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line, number)| {
+                let top = i32::try_from(line).unwrap() * 7 + 1;
+                number_pixels(*number).into_iter().map(move |(x, y)| {
+                    let size = STATS_PIXEL_SIZE;
+                    Rect::new((x + 1) * size, (y + top) * size, size as u32, size as u32)
+                })
+            })
+            .collect();
+        self.canvas.set_draw_color(self.palette[1]);
+        self.canvas.fill_rects(&rects)
+    }
+}
+
+impl Display for Renderer {
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize) -> Result<(), String> {
+        let size = (
+            u32::try_from(width).unwrap(),
+            u32::try_from(height).unwrap(),
+        );
+        let texture_size = self.texture.as_ref().map(|texture| {
+            let query = texture.query();
+            (query.width, query.height)
+        });
+        if texture_size != Some(size) {
+            // The resolution changed (SCHIP hires), textures are not freed on drop
+            if let Some(texture) = self.texture.take() {
+                unsafe { texture.destroy() };
             }
+            let texture = self
+                .texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, size.0, size.1)
+                .map_err(|e| e.to_string())?;
+            self.texture = Some(texture);
+        }
+
+        let texture = self.texture.as_mut().unwrap();
+        let palette = &self.palette;
+        let highlight = &self.highlight;
+        texture.with_lock(None, |buffer, pitch| {
+            fill_texture(buffer, pitch, gfx, width, palette);
+            tint_pixels(buffer, pitch, highlight, width, COLLISION_COLOR);
+        })?;
+        self.canvas.copy(texture, None, None)?;
+        if self.stats_visible {
+            self.draw_stats()?;
         }
         self.canvas.present();
         Ok(())
     }
 
-    pub fn clear(&mut self) {
-        self.canvas.set_draw_color(BACKGROUND_COLOR);
+    fn clear(&mut self) -> Result<(), String> {
+        self.canvas.set_draw_color(self.palette[0]);
         self.canvas.clear();
+        self.canvas.present();
+        Ok(())
     }
 
-    fn draw_rect(
-        &mut self,
-        x: usize,
-        y: usize,
-        width: u16,
-        height: u16,
-        color: Color,
-    ) -> Result<(), String> {
-        let x = u16::try_from(x).unwrap();
-        let y = u16::try_from(y).unwrap();
-        self.canvas.set_draw_color(color);
-        self.canvas.fill_rect(Rect::new(
-            (x * SCALE).try_into().unwrap(),
-            (y * SCALE).try_into().unwrap(),
-            (width * SCALE).try_into().unwrap(),
-            (height * SCALE).try_into().unwrap(),
-        ))?;
-
-        // self.canvas.present();
+    fn toggle_stats(&mut self) {
+        self.stats_visible = !self.stats_visible;
+    }
 
-        Ok(())
+    fn set_highlight(&mut self, pixels: &[usize]) {
+        self.highlight.clear();
+        self.highlight.extend_from_slice(pixels);
     }
 
-    fn draw_dot(&mut self, x: usize, y: usize) -> Result<(), String> {
-        self.draw_rect(x, y, 1, 1, DRAWING_COLOR)?;
-        Ok(())
+    /** Shows the ROM name in the window title */
+    fn set_rom_name(&mut self, rom_name: &str) {
+        self.rom_name = rom_name.to_string();
+        self.update_title();
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.update_title();
+    }
+
+    fn frame_done(&mut self, cycles: u64) {
+        let stats = &mut self.stats;
+        stats.frames += 1;
+        let elapsed = stats.start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let millis = u64::try_from(elapsed.as_millis()).unwrap();
+            stats.ips = (cycles - stats.start_cycles) * 1000 / millis;
+            stats.fps = stats.frames;
+            stats.start = Instant::now();
+            stats.start_cycles = cycles;
+            stats.frames = 0;
+        }
+    }
+
+    /** Saves the framebuffer as a PNG with the window scale and palette */
+    fn save_screenshot(
+        &self,
+        gfx: &[u8],
+        width: usize,
+        height: usize,
+        path: &Path,
+    ) -> Result<(), String> {
+        let image = screenshot_image(gfx, width, height, self.scale, &self.palette);
+        write_png(&image, path)
     }
 }
 
+// Keypad layout mapped onto the left side of a QWERTY keyboard
+const KEYMAP: [Keycode; 16] = [
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Num4,
+    Keycode::Q,
+    Keycode::W,
+    Keycode::E,
+    Keycode::R,
+    Keycode::A,
+    Keycode::S,
+    Keycode::D,
+    Keycode::F,
+    Keycode::Z,
+    Keycode::X,
+    Keycode::C,
+    Keycode::V,
+];
+
+fn key_index(keycode: Keycode) -> Option<u8> {
+    let index = KEYMAP.iter().position(|key| *key == keycode)?;
+    Some(u8::try_from(index).unwrap())
+}
+
 pub struct Keyboard {
     event_pump: EventPump,
+    // build the keypad from the keys currently held instead of key up/down events
+    snapshot: bool,
+    // F12 / PrintScreen was pressed since the last check
+    screenshot: bool,
+    // F3 was pressed since the last check
+    stats_toggle: bool,
+    // F4 was pressed since the last check
+    collision_debug_toggle: bool,
+    // F1 (warm) or Shift+F1 (cold) was pressed since the last check
+    reset: Option<bool>,
+    // Tab is held down
+    turbo: bool,
 }
 
 impl Keyboard {
-    pub fn new(sdl_context: Sdl) -> Keyboard {
-        let event_pump = sdl_context.event_pump().unwrap();
-        Keyboard { event_pump }
+    pub fn new(sdl_context: Sdl) -> Result<Keyboard, String> {
+        let event_pump = sdl_context
+            .event_pump()
+            .map_err(|e| format!("could not initialize event pump: {}", e))?;
+        Ok(Keyboard {
+            event_pump,
+            snapshot: false,
+            screenshot: false,
+            stats_toggle: false,
+            collision_debug_toggle: false,
+            reset: None,
+            turbo: false,
+        })
+    }
+
+    pub fn set_snapshot(&mut self, snapshot: bool) {
+        self.snapshot = snapshot;
+    }
+}
+
+impl Input for Keyboard {
+    /** Returns whether a screenshot was requested, and resets the request */
+    fn screenshot_requested(&mut self) -> bool {
+        std::mem::take(&mut self.screenshot)
+    }
+
+    /** Returns whether F4 was pressed, and resets the request */
+    fn collision_debug_toggle_requested(&mut self) -> bool {
+        std::mem::take(&mut self.collision_debug_toggle)
     }
 
-    pub fn keys_pressed(&mut self, keys: &mut [u8; 16], state: &mut ChipState) {
+    /** Returns whether F1 (warm) or Shift+F1 (cold) was pressed, and resets the request */
+    fn reset_requested(&mut self) -> Option<bool> {
+        self.reset.take()
+    }
+
+    /** Whether the rewind key (Backspace) is held down */
+    fn rewind_held(&self) -> bool {
+        self.event_pump
+            .keyboard_state()
+            .is_scancode_pressed(Scancode::Backspace)
+    }
+
+    /** Whether the turbo key (Tab) is held down */
+    fn turbo_held(&self) -> bool {
+        self.turbo
+    }
+
+    /** Returns whether the stats overlay was toggled, and resets the request */
+    fn stats_toggle_requested(&mut self) -> bool {
+        std::mem::take(&mut self.stats_toggle)
+    }
+
+    /** Updates the keypad and handles the quit/pause events.
+     * In snapshot mode a key pressed and released between two polls is still reported as
+     * pressed for one frame, so FX0A sees quick taps. */
+    fn poll(&mut self, keys: &mut Keypad, state: &mut ChipState) {
+        let mut tapped = Keypad::default();
         for event in self.event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => *state = ChipState::Quit,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    *state = if *state == ChipState::Pause {
+                        ChipState::Run
+                    } else {
+                        ChipState::Pause
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12 | Keycode::PrintScreen),
+                    ..
+                } => self.screenshot = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => self.stats_toggle = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => self.collision_debug_toggle = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    keymod,
+                    ..
+                } => self.reset = Some(keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => self.turbo = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => self.turbo = false,
                 Event::KeyDown {
                     keycode: Some(keycode),
                     ..
-                } => match keycode {
-                    Keycode::Num1 => keys[0] = 1,
-                    Keycode::Num2 => keys[1] = 1,
-                    Keycode::Num3 => keys[2] = 1,
-                    Keycode::Num4 => keys[3] = 1,
-
-                    Keycode::Q => keys[4] = 1,
-                    Keycode::W => keys[5] = 1,
-                    Keycode::E => keys[6] = 1,
-                    Keycode::R => keys[7] = 1,
-
-                    Keycode::A => keys[8] = 1,
-                    Keycode::S => keys[9] = 1,
-                    Keycode::D => keys[10] = 1,
-                    Keycode::F => keys[11] = 1,
-
-                    Keycode::Z => keys[12] = 1,
-                    Keycode::X => keys[13] = 1,
-                    Keycode::C => keys[14] = 1,
-                    Keycode::V => keys[15] = 1,
-                    Keycode::Space => {
-                        *state = if *state == ChipState::Pause {
-                            ChipState::Run
-                        } else {
-                            ChipState::Pause
-                        }
+                } => {
+                    if let Some(key) = key_index(keycode) {
+                        keys.press(key);
+                        tapped.press(key);
                     }
-                    _ => {}
-                },
+                }
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
-                } => match keycode {
-                    Keycode::Num1 => keys[0] = 0,
-                    Keycode::Num2 => keys[1] = 0,
-                    Keycode::Num3 => keys[2] = 0,
-                    Keycode::Num4 => keys[3] = 0,
-
-                    Keycode::Q => keys[4] = 0,
-                    Keycode::W => keys[5] = 0,
-                    Keycode::E => keys[6] = 0,
-                    Keycode::R => keys[7] = 0,
-
-                    Keycode::A => keys[8] = 0,
-                    Keycode::S => keys[9] = 0,
-                    Keycode::D => keys[10] = 0,
-                    Keycode::F => keys[11] = 0,
-
-                    Keycode::Z => keys[12] = 0,
-                    Keycode::X => keys[13] = 0,
-                    Keycode::C => keys[14] = 0,
-                    Keycode::V => keys[15] = 0,
-                    _ => {}
-                },
+                } => {
+                    if let Some(key) = key_index(keycode) {
+                        keys.release(key);
+                    }
+                }
                 _ => {}
             }
         }
-        // println!("keys: {:?}", keys);
+
+        if self.snapshot {
+            let keyboard_state = self.event_pump.keyboard_state();
+            for (key, keycode) in (0..).zip(KEYMAP.iter()) {
+                let held = Scancode::from_keycode(*keycode)
+                    .is_some_and(|scancode| keyboard_state.is_scancode_pressed(scancode));
+                keys.set(key, held || tapped.is_pressed(key));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn key_index_keymap() {
+        assert_eq!(key_index(Keycode::Num1), Some(0));
+        assert_eq!(key_index(Keycode::R), Some(7));
+        assert_eq!(key_index(Keycode::V), Some(15));
+        assert_eq!(key_index(Keycode::P), None);
+    }
+
+    #[test]
+    fn window_titles() {
+        assert_eq!(window_title("pong.ch8", false), "Chip-8 — pong.ch8");
+        assert_eq!(window_title("pong.ch8", true), "Chip-8 — pong.ch8 [Paused]");
+        assert_eq!(window_title("", false), "Chip-8");
+    }
+
+    #[test]
+    fn fill_texture_colors() {
+        let foreground = Color::RGB(0xff, 0xb0, 0x00);
+        let background = Color::RGB(0x10, 0x20, 0x30);
+        let gfx = [1, 0, 0, 1];
+        // 2x2 display with a padded pitch
+        let mut buffer = [0u8; 16];
+
+        let palette = [background, foreground, Color::BLACK, Color::BLACK];
+
+        fill_texture(&mut buffer, 8, &gfx, 2, &palette);
+
+        assert_eq!(
+            buffer,
+            [
+                0xff, 0xb0, 0x00, 0x10, 0x20, 0x30, 0, 0, //
+                0x10, 0x20, 0x30, 0xff, 0xb0, 0x00, 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_texture_planes() {
+        let palette = [
+            Color::RGB(0, 0, 0),
+            Color::RGB(1, 1, 1),
+            Color::RGB(2, 2, 2),
+            Color::RGB(3, 3, 3),
+        ];
+        let gfx = [0, 1, 2, 3];
+        let mut buffer = [0u8; 12];
+
+        fill_texture(&mut buffer, 12, &gfx, 4, &palette);
+
+        assert_eq!(buffer, [0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3]);
+    }
+
+    #[test]
+    fn screenshot_image_scale_and_palette() {
+        let foreground = Color::RGB(0xff, 0xb0, 0x00);
+        let background = Color::RGB(0x10, 0x20, 0x30);
+        let mut gfx = [0u8; 64 * 32];
+        gfx[64 + 2] = 1;
+
+        let palette = [background, foreground, Color::BLACK, Color::BLACK];
+
+        let image = screenshot_image(&gfx, 64, 32, 3, &palette);
+
+        assert_eq!(image.dimensions(), (192, 96));
+        assert_eq!(*image.get_pixel(6, 3), Rgb([0xff, 0xb0, 0x00]));
+        assert_eq!(*image.get_pixel(8, 5), Rgb([0xff, 0xb0, 0x00]));
+        assert_eq!(*image.get_pixel(9, 5), Rgb([0x10, 0x20, 0x30]));
+        assert_eq!(*image.get_pixel(5, 3), Rgb([0x10, 0x20, 0x30]));
+    }
+
+    #[test]
+    fn screenshot_image_hires() {
+        let mut gfx = [0u8; 128 * 64];
+        gfx[63 * 128 + 127] = 1;
+
+        let palette = [Color::BLACK, Color::WHITE, Color::BLACK, Color::BLACK];
+
+        let image = screenshot_image(&gfx, 128, 64, 2, &palette);
+
+        // each hires pixel covers a single window pixel at scale 2
+        assert_eq!(image.dimensions(), (128, 64));
+        assert_eq!(*image.get_pixel(127, 63), Rgb([0xff, 0xff, 0xff]));
+        assert_eq!(*image.get_pixel(126, 63), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn number_pixels_digits() {
+        // "1" is 0x20, 0x60, 0x20, 0x20, 0x70
+        assert_eq!(
+            number_pixels(1),
+            vec![
+                (2, 0),
+                (1, 1),
+                (2, 1),
+                (2, 2),
+                (2, 3),
+                (1, 4),
+                (2, 4),
+                (3, 4)
+            ]
+        );
+
+        // the second digit starts 5 pixels to the right
+        let pixels = number_pixels(71);
+        assert!(pixels.contains(&(0, 0)));
+        assert!(pixels.contains(&(7, 0)));
+        assert!(!pixels.contains(&(4, 0)));
+    }
+
+    #[test]
+    fn write_png_unwritable_path() {
+        let image = RgbImage::new(1, 1);
+
+        let result = write_png(&image, Path::new("/nonexistent/screenshot.png"));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_color_hex() {
+        assert_eq!(parse_color("#FFB000"), Ok(Color::RGB(0xff, 0xb0, 0x00)));
+        assert_eq!(parse_color("33ff66"), Ok(Color::RGB(0x33, 0xff, 0x66)));
+    }
+
+    #[test]
+    fn parse_color_invalid() {
+        assert!(parse_color("#FFB00").is_err());
+        assert!(parse_color("amber!").is_err());
+        assert!(parse_color("").is_err());
+        assert!(parse_color("+FFFFF").is_err());
+    }
+
+    #[test]
+    fn theme_colors() {
+        assert_eq!(Theme::Classic.colors(), (Color::WHITE, Color::BLACK));
+        assert_eq!(
+            Theme::GameBoy.colors(),
+            (
+                parse_color("0F380F").unwrap(),
+                parse_color("9BBC0F").unwrap()
+            )
+        );
+        assert_eq!(
+            Theme::Amber.colors(),
+            (
+                parse_color("FFB000").unwrap(),
+                parse_color("1A1000").unwrap()
+            )
+        );
+        assert_eq!("gameboy".parse(), Ok(Theme::GameBoy));
+        assert_eq!(
+            "neon".parse::<Theme>(),
+            Err("invalid theme: neon".to_string())
+        );
     }
 }
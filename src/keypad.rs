@@ -0,0 +1,79 @@
+/** State of the 16 key hex keypad (keys 0x0 - 0xF) */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Keypad {
+    keys: [bool; 16],
+}
+
+impl Keypad {
+    /** Presses a key, keys above 0xF do not exist and are ignored */
+    pub fn press(&mut self, key: u8) {
+        self.set(key, true);
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.set(key, false);
+    }
+
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        if let Some(state) = self.keys.get_mut(usize::from(key)) {
+            *state = pressed;
+        }
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys.get(usize::from(key)).copied().unwrap_or(false)
+    }
+
+    /** The lowest key that is pressed here but not in `held` */
+    pub fn newly_pressed(&self, held: &Keypad) -> Option<u8> {
+        (0..16).find(|&key| self.is_pressed(key) && !held.is_pressed(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn press_release() {
+        let mut keypad = Keypad::default();
+        assert!(!keypad.is_pressed(0x5));
+
+        keypad.press(0x5);
+        keypad.press(0xF);
+
+        assert!(keypad.is_pressed(0x5));
+        assert!(keypad.is_pressed(0xF));
+        assert!(!keypad.is_pressed(0x6));
+
+        keypad.release(0x5);
+
+        assert!(!keypad.is_pressed(0x5));
+        assert!(keypad.is_pressed(0xF));
+    }
+
+    #[test]
+    fn keys_out_of_range() {
+        let mut keypad = Keypad::default();
+
+        keypad.press(0x10);
+
+        assert_eq!(keypad, Keypad::default());
+        assert!(!keypad.is_pressed(0xFF));
+    }
+
+    #[test]
+    fn newly_pressed() {
+        let mut held = Keypad::default();
+        held.press(0x2);
+        let mut keypad = held;
+
+        assert_eq!(keypad.newly_pressed(&held), None);
+
+        keypad.press(0xA);
+        keypad.press(0x7);
+
+        assert_eq!(keypad.newly_pressed(&held), Some(0x7));
+    }
+}
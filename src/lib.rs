@@ -0,0 +1,15 @@
+pub mod asm;
+#[cfg(feature = "native")]
+pub mod audio;
+pub mod chip8;
+#[cfg(feature = "native")]
+pub mod cli;
+pub mod frontend;
+pub mod instruction;
+#[cfg(feature = "native")]
+pub mod io;
+pub mod keypad;
+pub mod menu;
+pub mod terminal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,21 +1,124 @@
-mod chip8;
-mod io;
+use std::fs;
 
-use chip8::Chip8;
-use io::IOContext;
+use chip_8::chip8::{Chip8, DrawQuirk, LOAD_ADDRESS};
+use chip_8::cli;
+use chip_8::frontend::{self, Display, Input, Sound};
+use chip_8::instruction;
+use chip_8::io::IOContext;
+use chip_8::menu::{self, Menu};
+use chip_8::terminal::{TerminalInput, TerminalRenderer};
 
 pub fn main() -> Result<(), String> {
-    let path_str = std::env::args().nth(1).expect("no path given");
-    let path = std::path::PathBuf::from(path_str);
+    // Instruction traces with RUST_LOG=trace
+    env_logger::init();
+
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // Only write the ROM listing, without running it
+    if let Some(path) = &options.disasm {
+        let rom = fs::read(&options.rom)
+            .map_err(|e| format!("could not read {}: {}", options.rom.display(), e))?;
+        return fs::write(path, instruction::listing(&rom, LOAD_ADDRESS))
+            .map_err(|e| format!("could not write {}: {}", path.display(), e));
+    }
+
+    // A directory opens a menu of its ROMs
+    let menu = if options.rom.is_dir() {
+        Some(Menu::new(menu::scan_roms(&options.rom)?)?)
+    } else {
+        None
+    };
+
+    if options.terminal {
+        // No window, so no SDL: the terminal takes the keyboard and the game plays silently
+        let mut display = TerminalRenderer::new();
+        let mut input = TerminalInput::new();
+        display.clear()?;
+        return run(&options, menu, &mut display, &mut input, &mut ());
+    }
 
     // Screen setup (sdl2)
-    let mut io_context = IOContext::new()?;
+    let mut io_context = IOContext::new(
+        options.scale,
+        options.foreground,
+        options.background,
+        &frontend::rom_name(&options.rom),
+        options.vsync,
+    )?;
+    io_context.keyboard.set_snapshot(options.key_snapshot);
+    io_context.audio.set_frequency(options.beep_frequency);
+    io_context.audio.set_waveform(options.waveform);
+
+    let IOContext {
+        renderer,
+        keyboard,
+        audio,
+    } = &mut io_context;
+    run(&options, menu, renderer, keyboard, audio)
+}
+
+/** Picks the ROM from the menu if there is one, then runs it on the given frontend */
+fn run(
+    options: &cli::Options,
+    menu: Option<Menu>,
+    display: &mut impl Display,
+    input: &mut impl Input,
+    sound: &mut impl Sound,
+) -> Result<(), String> {
+    let rom = match menu {
+        Some(menu) => match menu::choose(menu, display, input)? {
+            Some(rom) => rom,
+            // Quit from the menu
+            None => return Ok(()),
+        },
+        None => options.rom.clone(),
+    };
+    display.set_rom_name(&frontend::rom_name(&rom));
 
     // Initialize the Chip8 system and load the game into the memory
     let mut chip8 = Chip8::new();
-    chip8.load_game(path);
+    chip8.set_profile(options.profile);
+    if options.xo_chip {
+        chip8.set_xo_chip(true);
+    }
+    chip8.set_cycles_per_frame(options.speed);
+    if let Some(hz) = options.clock_hz {
+        chip8.set_clock_hz(hz);
+    }
+    chip8.set_vip_timing(options.vip_timing);
+    chip8.set_turbo_factor(options.turbo_factor);
+    if options.shift_quirk {
+        chip8.set_shift_quirk(true);
+    }
+    if options.draw_quirk != DrawQuirk::default() {
+        chip8.set_draw_quirk(options.draw_quirk);
+    }
+    if options.display_wait_quirk {
+        chip8.set_display_wait_quirk(true);
+    }
+    chip8.set_protect_reserved(options.protect_reserved);
+    chip8.set_decode_cache(true);
+    chip8.set_watchdog(options.watchdog);
+    chip8.load_game(rom)?;
+    if let Some(path) = &options.flags {
+        chip8.load_flags(path)?;
+    }
+    if options.start_paused {
+        // Space resumes
+        chip8.pause();
+    }
+
+    chip8.run_loop(display, input, sound)?;
 
-    chip8.run_loop(&mut io_context)?;
+    if let Some(path) = &options.flags {
+        chip8.save_flags(path)?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::chip8::{ChipState, CHIP8_FONT_SET};
+use crate::frontend::{self, Display, Input};
+use crate::keypad::Keypad;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+// Rows of 5 pixel high digits with a 1 pixel gap
+const ROW_HEIGHT: usize = 6;
+const ROWS: usize = HEIGHT / ROW_HEIGHT;
+// Keypad keys moving the cursor and picking the ROM
+const KEY_UP: u8 = 0x2;
+const KEY_DOWN: u8 = 0x8;
+const KEY_SELECT: u8 = 0x5;
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/** The .ch8 and .c8 files of a directory, sorted by name */
+pub fn scan_roms(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|extension| {
+                    extension.eq_ignore_ascii_case("ch8") || extension.eq_ignore_ascii_case("c8")
+                })
+        })
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+/** A list of ROMs to pick from with the keypad: 2 and 8 move the cursor, 5 picks the ROM.
+ * The font only has hex digits, so the ROMs are listed by number and the selected ROM name
+ * is shown by the front-end (the window title) */
+pub struct Menu {
+    roms: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new(roms: Vec<PathBuf>) -> Result<Menu, String> {
+        if roms.is_empty() {
+            return Err("no ROMs (.ch8 or .c8 files) to choose from".to_string());
+        }
+        Ok(Menu { roms, selected: 0 })
+    }
+
+    pub fn selected(&self) -> &Path {
+        &self.roms[self.selected]
+    }
+
+    /** Handles a keypad key, returns the ROM picked with the select key */
+    pub fn press(&mut self, key: u8) -> Option<&Path> {
+        match key {
+            KEY_UP => self.selected = self.selected.saturating_sub(1),
+            KEY_DOWN => self.selected = (self.selected + 1).min(self.roms.len() - 1),
+            KEY_SELECT => return Some(self.selected()),
+            _ => {}
+        }
+        None
+    }
+
+    /** The 64x32 screen listing the page of the selected ROM, numbered from 1, with a cursor
+     * in front of the selected one */
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let mut gfx = vec![0; WIDTH * HEIGHT];
+        let first = self.selected / ROWS * ROWS;
+        for (row, index) in (first..self.roms.len().min(first + ROWS)).enumerate() {
+            let top = row * ROW_HEIGHT + 1;
+            if index == self.selected {
+                // a 3x5 arrow
+                for (y, width) in [1, 2, 3, 2, 1].into_iter().enumerate() {
+                    for x in 0..width {
+                        gfx[(top + y) * WIDTH + 2 + x] = 1;
+                    }
+                }
+            }
+            for (position, digit) in format!("{:X}", index + 1).chars().enumerate() {
+                let digit = usize::try_from(digit.to_digit(16).unwrap()).unwrap();
+                let left = 8 + position * 5;
+                for (y, bits) in CHIP8_FONT_SET[digit * 5..digit * 5 + 5].iter().enumerate() {
+                    for x in 0..4 {
+                        if bits & (0x80 >> x) != 0 && left + x < WIDTH {
+                            gfx[(top + y) * WIDTH + left + x] = 1;
+                        }
+                    }
+                }
+            }
+        }
+        gfx
+    }
+}
+
+/** Shows the menu until a ROM is picked, or returns None when the window is closed */
+pub fn choose(
+    mut menu: Menu,
+    display: &mut impl Display,
+    input: &mut impl Input,
+) -> Result<Option<PathBuf>, String> {
+    let mut keys = Keypad::default();
+    let mut held = Keypad::default();
+    let mut state = ChipState::Run;
+    let mut changed = true;
+    loop {
+        if changed {
+            display.set_rom_name(&frontend::rom_name(menu.selected()));
+            display.draw(&menu.framebuffer(), WIDTH, HEIGHT)?;
+        }
+
+        input.poll(&mut keys, &mut state);
+        if state == ChipState::Quit {
+            return Ok(None);
+        }
+        let selected = menu.selected;
+        if let Some(key) = keys.newly_pressed(&held) {
+            if let Some(rom) = menu.press(key) {
+                return Ok(Some(rom.to_path_buf()));
+            }
+        }
+        changed = menu.selected != selected;
+        held = keys;
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn scan_roms_in_directory() {
+        let dir = std::env::temp_dir().join(format!("chip-8-menu-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested.ch8")).unwrap();
+        for name in ["tetris.c8", "Pong.CH8", "README.txt", "breakout.ch8"] {
+            fs::write(dir.join(name), [0x12, 0x00]).unwrap();
+        }
+
+        let roms = scan_roms(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            roms,
+            Ok(vec![
+                dir.join("Pong.CH8"),
+                dir.join("breakout.ch8"),
+                dir.join("tetris.c8"),
+            ])
+        );
+    }
+
+    #[test]
+    fn scan_empty_directory() {
+        let dir = std::env::temp_dir().join(format!("chip-8-empty-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let roms = scan_roms(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(roms, Ok(vec![]));
+        assert!(Menu::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn menu_navigation() {
+        let roms: Vec<PathBuf> = (1..=7)
+            .map(|n| PathBuf::from(format!("{}.ch8", n)))
+            .collect();
+        let mut menu = Menu::new(roms).unwrap();
+
+        assert_eq!(menu.press(KEY_UP), None);
+        assert_eq!(menu.selected(), Path::new("1.ch8"));
+
+        for _ in 0..10 {
+            menu.press(KEY_DOWN);
+        }
+        menu.press(KEY_UP);
+
+        assert_eq!(menu.press(KEY_SELECT), Some(Path::new("6.ch8")));
+    }
+
+    #[test]
+    fn menu_framebuffer() {
+        let roms: Vec<PathBuf> = (1..=7)
+            .map(|n| PathBuf::from(format!("{}.ch8", n)))
+            .collect();
+        let mut menu = Menu::new(roms).unwrap();
+        menu.press(KEY_DOWN);
+
+        let gfx = menu.framebuffer();
+
+        // the cursor is on the second row, the "1" of the first row starts at x = 8
+        assert_eq!(gfx[(ROW_HEIGHT + 1) * WIDTH + 2], 1);
+        assert_eq!(gfx[WIDTH + 2], 0);
+        assert_eq!(gfx[WIDTH + 8..WIDTH + 12], [0, 0, 1, 0]);
+
+        // the second page only lists the 6th and 7th ROMs
+        for _ in 0..5 {
+            menu.press(KEY_DOWN);
+        }
+        let gfx = menu.framebuffer();
+        assert!(gfx[(2 * ROW_HEIGHT + 1) * WIDTH..]
+            .iter()
+            .all(|&pixel| pixel == 0));
+    }
+}
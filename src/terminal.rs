@@ -0,0 +1,256 @@
+use std::io::{self, Read, Stdin, Stdout, Write};
+use std::process::{Command, Stdio};
+
+use crate::chip8::ChipState;
+use crate::frontend::{Display, Input};
+use crate::keypad::Keypad;
+
+// ANSI escape sequences
+const CLEAR_SCREEN: &str = "\x1b[2J";
+const CURSOR_HOME: &str = "\x1b[H";
+
+// Keypad layout mapped onto the left side of a QWERTY keyboard, like the window
+const KEYMAP: [u8; 16] = *b"1234qwerasdfzxcv";
+// Terminals only send key presses, repeated while the key is held, so a key is released
+// when no press came for this many frames (the usual auto repeat delay)
+const KEY_HOLD_FRAMES: u32 = 30;
+const CTRL_C: u8 = 0x03;
+
+/** Draws the display as block characters on a terminal, for running over SSH or in CI.
+ * Each character cell holds two pixel rows */
+pub struct TerminalRenderer<W: Write = Stdout> {
+    out: W,
+}
+
+impl TerminalRenderer {
+    pub fn new() -> TerminalRenderer {
+        TerminalRenderer::with_writer(io::stdout())
+    }
+}
+
+impl Default for TerminalRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Write> TerminalRenderer<W> {
+    pub fn with_writer(out: W) -> TerminalRenderer<W> {
+        TerminalRenderer { out }
+    }
+}
+
+impl<W: Write> Display for TerminalRenderer<W> {
+    /** Redraws the whole display over the previous frame */
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize) -> Result<(), String> {
+        let frame = frame(gfx, width, height);
+        write!(self.out, "{}{}", CURSOR_HOME, frame)
+            .and_then(|_| self.out.flush())
+            .map_err(|e| e.to_string())
+    }
+
+    fn clear(&mut self) -> Result<(), String> {
+        write!(self.out, "{}{}", CLEAR_SCREEN, CURSOR_HOME)
+            .and_then(|_| self.out.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/** Reads the keypad from the keys typed on a terminal: Space pauses and Ctrl+C quits. Until it
+ * is dropped, stdin is switched to unbuffered non blocking reads without echo (with `stty`, so
+ * on Unix terminals) */
+pub struct TerminalInput<R: Read = Stdin> {
+    input: R,
+    // frames left before each keypad key is released
+    held: [u32; 16],
+    // `stty -g` settings restored on drop, None when stdin is not a terminal
+    saved_mode: Option<String>,
+}
+
+impl TerminalInput {
+    pub fn new() -> TerminalInput {
+        let mut input = TerminalInput::with_reader(io::stdin());
+        input.saved_mode = stty(&["-g"])
+            .filter(|_| stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "0"]).is_some());
+        input
+    }
+}
+
+impl Default for TerminalInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Read> TerminalInput<R> {
+    pub fn with_reader(input: R) -> TerminalInput<R> {
+        TerminalInput {
+            input,
+            held: [0; 16],
+            saved_mode: None,
+        }
+    }
+}
+
+impl<R: Read> Drop for TerminalInput<R> {
+    fn drop(&mut self) {
+        if let Some(mode) = &self.saved_mode {
+            stty(&[mode]);
+        }
+    }
+}
+
+impl<R: Read> Input for TerminalInput<R> {
+    /** Takes the bytes typed since the last poll, a read returning nothing ends them */
+    fn poll(&mut self, keys: &mut Keypad, state: &mut ChipState) {
+        for held in self.held.iter_mut() {
+            *held = held.saturating_sub(1);
+        }
+        let mut buffer = [0; 64];
+        while let Ok(length @ 1..) = self.input.read(&mut buffer) {
+            for byte in &buffer[..length] {
+                match byte.to_ascii_lowercase() {
+                    CTRL_C => *state = ChipState::Quit,
+                    b' ' => {
+                        *state = if *state == ChipState::Pause {
+                            ChipState::Run
+                        } else {
+                            ChipState::Pause
+                        }
+                    }
+                    byte => {
+                        if let Some(key) = KEYMAP.iter().position(|key| *key == byte) {
+                            self.held[key] = KEY_HOLD_FRAMES;
+                        }
+                    }
+                }
+            }
+        }
+        for (key, held) in (0..).zip(self.held) {
+            keys.set(key, held > 0);
+        }
+    }
+}
+
+/** Runs stty on the terminal of stdin, returns its output or None when it fails */
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/** The display as lines of half block characters, a pixel being lit in any plane */
+fn frame(gfx: &[u8], width: usize, height: usize) -> String {
+    let mut frame = String::with_capacity((width * 3 + 1) * height / 2);
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let top = gfx[y * width + x] != 0;
+            let bottom = y + 1 < height && gfx[(y + 1) * width + x] != 0;
+            frame.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        frame.push('\n');
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn frame_half_blocks() {
+        // 4x4 display
+        let gfx = [
+            1, 0, 1, 0, //
+            1, 1, 0, 0, //
+            0, 0, 0, 2, //
+            0, 0, 0, 1,
+        ];
+
+        assert_eq!(frame(&gfx, 4, 4), "█▄▀ \n   █\n");
+    }
+
+    #[test]
+    fn draw_moves_cursor_home() {
+        let mut renderer = TerminalRenderer::with_writer(Vec::new());
+
+        renderer.clear().unwrap();
+        renderer.draw(&[1, 0], 2, 1).unwrap();
+
+        assert_eq!(
+            String::from_utf8(renderer.out).unwrap(),
+            "\x1b[2J\x1b[H\x1b[H▀ \n"
+        );
+    }
+
+    #[test]
+    fn input_holds_typed_keys() {
+        let mut input = TerminalInput::with_reader(&b"1V "[..]);
+        let mut keys = Keypad::default();
+        let mut state = ChipState::Run;
+
+        input.poll(&mut keys, &mut state);
+
+        assert!(keys.is_pressed(0x0));
+        assert!(keys.is_pressed(0xF));
+        assert_eq!(state, ChipState::Pause);
+
+        for _ in 1..KEY_HOLD_FRAMES {
+            input.poll(&mut keys, &mut state);
+        }
+        assert!(keys.is_pressed(0x0));
+        input.poll(&mut keys, &mut state);
+        assert!(!keys.is_pressed(0x0));
+    }
+
+    /** Returns one chunk of bytes per read, an empty chunk ends a poll */
+    struct ScriptedReader(Vec<&'static [u8]>);
+
+    impl Read for ScriptedReader {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.0.remove(0);
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn run_loop_on_terminal() {
+        // Terminal mode without SDL: this module builds without the native feature
+        let mut chip8 = Chip8::deterministic(0);
+        chip8
+            .load_game_from_bytes(&[
+                0xf0, 0x0a, // V0 = key
+                0xf0, 0x29, // I = sprite of V0
+                0xd1, 0x15, // draw it at (0, 0)
+                0x12, 0x06, // loop
+            ])
+            .unwrap();
+        let mut display = TerminalRenderer::with_writer(Vec::new());
+        // a frame of waiting, key 7 and Ctrl+C on the next frame
+        let script: Vec<&'static [u8]> = vec![b"", b"r", b"", b"\x03"];
+        let mut input = TerminalInput::with_reader(ScriptedReader(script));
+
+        chip8.run_loop(&mut display, &mut input, &mut ()).unwrap();
+
+        // the first two rows of "7", 0xF0 and 0x10
+        let output = String::from_utf8(display.out).unwrap();
+        assert!(output.contains(&format!("{}▀▀▀█ ", CURSOR_HOME)));
+    }
+}
@@ -0,0 +1,85 @@
+use wasm_bindgen::prelude::*;
+
+use crate::chip8::Chip8;
+
+/** The machine as seen from JavaScript. The page calls `step` on every animation frame,
+ * draws `framebuffer` and forwards the key events */
+#[wasm_bindgen]
+pub struct WasmChip8 {
+    chip8: Chip8,
+}
+
+#[wasm_bindgen]
+impl WasmChip8 {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmChip8 {
+        WasmChip8 {
+            chip8: Chip8::new(),
+        }
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        self.chip8.load_game_from_bytes(rom)
+    }
+
+    /** Runs one 60Hz frame */
+    pub fn step(&mut self) {
+        self.chip8.step();
+    }
+
+    /** One byte per pixel, row by row */
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.chip8.framebuffer().to_vec()
+    }
+
+    pub fn width(&self) -> usize {
+        self.chip8.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.chip8.height
+    }
+
+    /** Whether the page should play the beep this frame */
+    pub fn is_beeping(&self) -> bool {
+        self.chip8.is_beeping()
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.chip8.press_key(key);
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.chip8.release_key(key);
+    }
+}
+
+impl Default for WasmChip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn run_rom() {
+        let mut chip8 = WasmChip8::new();
+        // V0 = key, draw its sprite, loop
+        chip8
+            .load_rom(&[0xf0, 0x0a, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x06])
+            .unwrap();
+
+        chip8.step();
+        chip8.press_key(0x1);
+        chip8.step();
+        chip8.release_key(0x1);
+
+        assert_eq!((chip8.width(), chip8.height()), (64, 32));
+        // "1" starts with 0x20
+        assert_eq!(chip8.framebuffer()[..4], [0, 0, 1, 0]);
+    }
+}
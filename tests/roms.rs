@@ -0,0 +1,61 @@
+//! Runs test ROMs headless and compares their display with golden images.
+//! Golden images are text files with a line per row, '#' for lit pixels and '.' for dark ones
+
+use chip_8::asm::assemble;
+use chip_8::chip8::{Chip8, ChipState};
+
+/** Packs a golden image into 1 bit per pixel rows */
+fn pack(golden: &str) -> Vec<u8> {
+    golden
+        .lines()
+        .flat_map(|line| {
+            line.as_bytes()
+                .chunks(8)
+                .map(|pixels| {
+                    pixels
+                        .iter()
+                        .fold(0, |byte, pixel| (byte << 1) | u8::from(*pixel == b'#'))
+                })
+                .collect::<Vec<u8>>()
+        })
+        .collect()
+}
+
+/** Runs the ROM for a number of 60Hz frames */
+fn run(rom: &[u8], frames: u32) -> Chip8 {
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.load_game_from_bytes(rom).unwrap();
+    for _ in 0..frames {
+        chip8.step();
+    }
+    chip8
+}
+
+fn assert_display(chip8: &Chip8, golden: &str) {
+    // the failure message shows both like `Chip8::framebuffer_ascii`, dark pixels as ' '
+    assert!(
+        chip8.framebuffer_packed() == pack(golden),
+        "display:\n{}\nexpected:\n{}",
+        chip8.framebuffer_ascii(),
+        golden.replace('.', " ")
+    );
+}
+
+#[test]
+fn ibm_logo() {
+    let chip8 = run(include_bytes!("roms/ibm_logo.ch8"), 60);
+
+    assert_eq!(chip8.state, ChipState::Run);
+    assert_display(&chip8, include_str!("golden/ibm_logo.txt"));
+}
+
+/** Opcode self test, the results it draws are computed by hand in opcodes.asm */
+#[test]
+fn opcodes() {
+    let rom = assemble(include_str!("roms/opcodes.asm")).unwrap();
+
+    let chip8 = run(&rom, 60);
+
+    assert_eq!(chip8.state, ChipState::Run);
+    assert_display(&chip8, include_str!("golden/opcodes.txt"));
+}
@@ -0,0 +1,162 @@
+; Opcode self test, assembled by the integration tests.
+; Each result is printed as a 3 digit decimal number, 4 numbers per row. The expected numbers
+; below are computed by hand, golden/opcodes.txt must show them:
+;
+;   44   1  56   0      8XY4 sum and carry, 8XY5 difference and borrow
+;  226   0  27   1      8XY7 difference and borrow, 8XY6 result and shifted out bit
+;  130   1 243  80      8XYE result and shifted out bit, 8XY1, 8XY2
+;  163   7  42 108      8XY3, 3XNN/4XNN/5XY0 skips taken and 9XY0 not taken, 2NNN/00EE, BNNN
+;   99  33              FX1E, FX55/FX65
+
+    LD VA, 1
+    LD VB, 1
+
+    ; 8XY4 with carry
+    LD V0, 200
+    LD V1, 100
+    ADD V0, V1
+    LD V5, VF
+    CALL print
+    LD V0, V5
+    CALL print
+
+    ; 8XY5 without borrow (VF = 1), then with borrow (VF = 0)
+    LD V0, 100
+    LD V1, 44
+    SUB V0, V1
+    CALL print
+    LD V0, 10
+    LD V1, 40
+    SUB V0, V1
+    LD V5, VF
+    LD V0, V5
+    CALL print
+
+    ; 8XY7 with borrow
+    LD V0, 30
+    LD V1, 0
+    SUBN V0, V1
+    LD V5, VF
+    CALL print
+    LD V0, V5
+    CALL print
+
+    ; 8XY6
+    LD V0, 55
+    SHR V0, V0
+    LD V5, VF
+    CALL print
+    LD V0, V5
+    CALL print
+
+    ; 8XYE
+    LD V0, 193
+    SHL V0, V0
+    LD V5, VF
+    CALL print
+    LD V0, V5
+    CALL print
+
+    ; 8XY1, 8XY2, 8XY3
+    LD V0, 0xF0
+    LD V1, 0x03
+    OR V0, V1
+    CALL print
+    LD V0, 0xF0
+    LD V1, 0x5A
+    AND V0, V1
+    CALL print
+    LD V0, 0xF0
+    LD V1, 0x53
+    XOR V0, V1
+    CALL print
+
+    ; skips, each taken one adds to V5
+    LD V5, 0
+    LD V1, 9
+    LD V2, 9
+    SE V1, 9
+    JP skip_1
+    ADD V5, 1
+skip_1:
+    SNE V1, 8
+    JP skip_2
+    ADD V5, 2
+skip_2:
+    SE V1, V2
+    JP skip_3
+    ADD V5, 4
+skip_3:
+    SNE V1, V2
+    JP skip_4
+    ADD V5, 8
+skip_4:
+    LD V0, V5
+    CALL print
+
+    ; 2NNN/00EE
+    LD V0, 0
+    CALL set_42
+    CALL print
+
+    ; BNNN
+    LD V0, 4
+    JP V0, jump_table
+jump_table:
+    LD V0, 1
+    JP jump_done
+    LD V0, 108
+jump_done:
+    CALL print
+
+    ; FX1E
+    LD I, scratch
+    LD V0, 99
+    ADD I, V0
+    LD [I], V0
+    LD I, scratch
+    LD V0, 99
+    ADD I, V0
+    LD V0, 0
+    LD V0, [I]
+    CALL print
+
+    ; FX55/FX65
+    LD I, scratch
+    LD V0, 33
+    LD V1, 44
+    LD [I], V1
+    LD V0, 0
+    LD V1, 0
+    LD V0, [I]
+    CALL print
+
+end:
+    JP end
+
+; Prints V0 as 3 digits at (VA, VB) and moves to the next number
+print:
+    LD I, scratch
+    LD B, V0
+    LD V2, [I]
+    LD F, V0
+    DRW VA, VB, 5
+    ADD VA, 5
+    LD F, V1
+    DRW VA, VB, 5
+    ADD VA, 5
+    LD F, V2
+    DRW VA, VB, 5
+    ADD VA, 6
+    SE VA, 65
+    RET
+    LD VA, 1
+    ADD VB, 6
+    RET
+
+set_42:
+    LD V0, 42
+    RET
+
+scratch:
+    DB 0 0 0